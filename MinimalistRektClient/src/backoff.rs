@@ -0,0 +1,62 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+/**
+ * Exponential backoff used between two reconnection attempts.
+ * The delay of the attempt n is `min(max_delay, initial_delay * multiplier^n)`,
+ * then a random part of it (up to `jitter` percent) is removed so that every
+ * client dropped by a broker restart don't come back at the same instant.
+ */
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub multiplier: f64,
+    pub jitter: f64, // 0.0 = no jitter, 1.0 = full jitter
+    pub max_attempts: Option<u32>, // None = retry forever
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            initial_delay: Duration::from_millis(250),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.5,
+            max_attempts: None,
+        }
+    }
+}
+
+impl Backoff {
+    /**
+     * This method return the time to wait before the given attempt.
+     *
+     * @param attempt: u32, number of failed attempts since the last successful connection (starting at 1)
+     *
+     * @return Duration
+     */
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(31) as i32;
+        let delay = (self.initial_delay.as_secs_f64() * self.multiplier.powi(exponent))
+            .min(self.max_delay.as_secs_f64());
+        let jitter = delay * self.jitter.clamp(0.0, 1.0) * rand::thread_rng().gen::<f64>();
+
+        Duration::from_secs_f64(delay - jitter)
+    }
+
+    /**
+     * This method return true when no more attempt should be done.
+     *
+     * @param attempt: u32, number of failed attempts since the last successful connection
+     *
+     * @return bool
+     */
+    pub fn is_exhausted(&self, attempt: u32) -> bool {
+        match self.max_attempts {
+            Some(max_attempts) => attempt >= max_attempts,
+            None => false,
+        }
+    }
+}
@@ -8,6 +8,16 @@ use std::sync::Arc;
 use log::{error, info};
 use pretty_logger::{Destination, Theme};
 use quinn::{ClientConfig, Connection, Endpoint};
+use rekt_lib::datagrams::topic_request::TOPIC_OPTION_NONE;
+
+use crate::backoff::Backoff;
+use crate::rekt_client::{ConnectionEvent, RektClient};
+use crate::tls::{build_client_crypto, parse_fingerprint, TlsVerification};
+
+mod backoff;
+mod rekt_client;
+mod tls;
+mod tests;
 
 static PAYLOAD_SIZE: usize = 1024;
 
@@ -16,16 +26,13 @@ static PAYLOAD_SIZE: usize = 1024;
 async fn main() -> Result<(), Box<dyn Error>> {
     pretty_logger::init(Destination::Stdout, "info".parse().unwrap(), Theme::default())?;
 
-    let crypto = rustls::ClientConfig::builder()
-        .with_safe_defaults()
-        .with_custom_certificate_verifier(SkipServerVerification::new())
-        .with_no_client_auth();
+    let crypto = build_client_crypto(&tls_verification_from_args()?)?;
 
     let client_config = ClientConfig::new(Arc::new(crypto));
 
     let mut iter: u64 = 0;
 
-    match client(client_config).await {
+    match client(client_config, &token_from_args()).await {
         Ok(_) => {
             info!("Client connexion successfully closed.");
         }
@@ -38,28 +45,37 @@ async fn main() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-async fn client(config: ClientConfig) -> Result<(), Box<dyn Error>> {
+async fn client(config: ClientConfig, token: &str) -> Result<(), Box<dyn Error>> {
     // Bind this endpoint to a UDP socket on the given client address.
     let mut endpoint = Endpoint::client(SocketAddr::from_str("127.0.0.1:6666")?)?;
     endpoint.set_default_client_config(config);
 
-
     // Connect to the server passing in the server name which is supposed to be in the server certificate.
-    let connection = endpoint.connect(SocketAddr::from_str("127.0.0.1:3838")?, "localhost")?.await?;
+    // The client reconnect by itself and replay its subscriptions when the connection is lost.
+    let (client, mut datagrams) = RektClient::new(endpoint, SocketAddr::from_str("127.0.0.1:3838")?, "localhost", token, Backoff::default());
+
+    let mut events = client.events();
+    tokio::spawn(async move {
+        while let Ok(event) = events.recv().await {
+            match event {
+                ConnectionEvent::Connected { remote, peer_id } => info!("Connected to {} as {}", remote, peer_id),
+                ConnectionEvent::Disconnected { reason } => info!("Disconnected : {}", reason),
+                event => info!("{:?}", event),
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        while let Some(received_bytes) = datagrams.recv().await {
+            println!("Unrealiable message recieved: {:?}", received_bytes);
+        }
+    });
 
-    // Start transferring, receiving data, see data transfer page.
-    // TODO : Implementing connection process on server
     // TODO : Implementing stream management on server + test message transfert
-    // TODO : Implementing disconnection
     // TODO : Implementing heartbeat + ping
     // TODO : Stress test 1
-    //receive_bidirectional_stream(connection).await;
-    connection.send_datagram(bytes::Bytes::from("Coucou from client"))?;
-    while let Ok(received_bytes) = connection.read_datagram().await {
-        // Because it is a unidirectional stream, we can only receive not send back.
-        println!("Unrealiable message recieved: {}", String::from_utf8(received_bytes.to_vec())?);
-    }
-
+    client.subscribe_topic(1, TOPIC_OPTION_NONE)?;
+    client.run().await?;
 
     Ok(())
 }
@@ -113,26 +129,52 @@ async fn receive_unidirectional_stream(connection: Connection) -> Result<(), Box
 }
 
 
-// Implementation of `ServerCertVerifier` that verifies everything as trustworthy.
-struct SkipServerVerification;
+/**
+ * Read the certificate verification mode from the command line :
+ *  --trust-store <file.pem> : trust the CA certificates of the file,
+ *  --pin <sha256>           : trust the certificate with this fingerprint (repeatable),
+ *  --insecure               : skip the verification (local development only).
+ * Without any of them, the broker certificate is verified against the public CA roots.
+ */
+fn tls_verification_from_args() -> Result<TlsVerification, Box<dyn Error>> {
+    let mut verification = TlsVerification::default();
+    let mut pins = Vec::new();
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--trust-store" => {
+                let path = args.next().ok_or("Missing file after --trust-store")?;
+                verification = TlsVerification::TrustStore(path.into());
+            }
+            "--pin" => {
+                let fingerprint = args.next().ok_or("Missing fingerprint after --pin")?;
+                pins.push(parse_fingerprint(&fingerprint)?);
+            }
+            "--insecure" => verification = TlsVerification::Insecure,
+            _ => {}
+        }
+    }
 
-impl SkipServerVerification {
-    fn new() -> Arc<Self> {
-        Arc::new(Self)
+    if !pins.is_empty() {
+        verification = TlsVerification::Pinned(pins);
     }
+
+    Ok(verification)
 }
 
-impl rustls::client::ServerCertVerifier for SkipServerVerification {
-    fn verify_server_cert(
-        &self,
-        _end_entity: &rustls::Certificate,
-        _intermediates: &[rustls::Certificate],
-        _server_name: &rustls::ServerName,
-        _scts: &mut dyn Iterator<Item=&[u8]>,
-        _ocsp_response: &[u8],
-        _now: std::time::SystemTime,
-    ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
-        Ok(rustls::client::ServerCertVerified::assertion())
+/**
+ * Read the authentication token from the command line : --token <token>.
+ * Without it, the client connect anonymously.
+ */
+fn token_from_args() -> String {
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        if arg == "--token" {
+            return args.next().unwrap_or_default();
+        }
     }
-}
 
+    String::new()
+}
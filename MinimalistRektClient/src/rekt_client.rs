@@ -0,0 +1,569 @@
+// This document contain the connection manager of the client. It keep the QUIC
+// connection to the broker alive: when the connection is lost (broker restart,
+// idle timeout...) it reconnect with an exponential backoff and replay every
+// active topic and object subscription. Each state change is broadcast as a
+// ConnectionEvent so the application can react to it. Topic histories,
+// durable messages and reliable topics sent by the broker through streams
+// are split and forwarded as plain datagrams. DtgData published with ack are
+// retransmitted until the broker acknowledge them, even across reconnections.
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use bytes::{Bytes, BytesMut};
+use log::{debug, info, warn};
+use quinn::{Connection, ConnectionError, Endpoint, RecvStream, SendDatagramError, VarInt};
+use rekt_lib::datagrams::connect_requests::{DtgConnect, DtgConnectAck, DtgConnectNack};
+use rekt_lib::datagrams::data_request::{DtgData, DtgDataAck};
+use rekt_lib::datagrams::heartbeat_requests::DtgHeartbeat;
+use rekt_lib::datagrams::latency_requests::{DtgPing, DtgPong};
+use rekt_lib::datagrams::object_requests::DtgObjectRequest;
+use rekt_lib::datagrams::topic_request::{DtgTopicRequest, TOPIC_OPTION_NONE};
+use rekt_lib::enums::datagram_type::DatagramType;
+use rekt_lib::enums::object_request_action::ObjectRequestAction;
+use rekt_lib::enums::topic_action::TopicAction;
+use rekt_lib::libs::types::{ClientId, ObjectId, TopicId, TopicOptions};
+use tokio::sync::{broadcast, mpsc};
+
+use crate::backoff::Backoff;
+
+// Size of the channels used to give events and datagrams to the application
+const EVENT_CHANNEL_SIZE: usize = 32;
+const DATAGRAM_CHANNEL_SIZE: usize = 1024;
+// Biggest chunk read at once from a stream
+const MAX_CHUNK_SIZE: usize = 64 * 1024;
+// Time given to the broker to acknowledge the connect request
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+// A DtgData published with ack is sent again when not acknowledged in time, and given up after MAX_PUBLISH_ATTEMPTS
+const ACK_TIMEOUT: Duration = Duration::from_millis(500);
+const RETRANSMIT_PERIOD: Duration = Duration::from_millis(100);
+const MAX_PUBLISH_ATTEMPTS: u32 = 10;
+
+/**
+ * ConnectionEvent are raised at each state change
+ * of the connection to the broker.
+ */
+#[derive(Debug, Clone)]
+pub enum ConnectionEvent {
+    Connecting { attempt: u32 },
+    Connected { remote: SocketAddr, peer_id: ClientId },
+    SubscriptionsRestored { topics: usize, objects: usize },
+    Disconnected { reason: String },
+    Reconnecting { attempt: u32, delay: Duration },
+    GaveUp { attempts: u32 },
+    PublishFailed { topic_id: TopicId, sequence_number: u32 },
+    Closed,
+}
+
+#[derive(Debug)]
+pub enum ClientError {
+    NotConnected,
+    SendDatagram(SendDatagramError),
+    ReconnectionFailed(u32),
+}
+
+impl Display for ClientError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientError::NotConnected => write!(f, "The client is not connected to the broker"),
+            ClientError::SendDatagram(err) => write!(f, "Failed to send the datagram : {}", err),
+            ClientError::ReconnectionFailed(attempts) => write!(f, "Broker still unreachable after {} attempts", attempts),
+        }
+    }
+}
+
+impl Error for ClientError {}
+
+impl From<SendDatagramError> for ClientError {
+    fn from(err: SendDatagramError) -> Self {
+        ClientError::SendDatagram(err)
+    }
+}
+
+// Active subscriptions, replayed after each reconnection
+#[derive(Debug, Default)]
+struct Subscriptions {
+    topics: HashMap<TopicId, TopicOptions>,
+    objects: HashSet<ObjectId>,
+}
+
+// A DtgData published with ack, waiting for its DtgDataAck
+#[derive(Debug)]
+struct PendingPublish {
+    datagram: Bytes,
+    sent_at: Instant,
+    attempts: u32, // 0 while it was never sent
+}
+
+pub struct RektClient {
+    endpoint: Endpoint,
+    server_addr: SocketAddr,
+    server_name: String,
+    token: String,
+    backoff: Backoff,
+    connection: RwLock<Option<Connection>>,
+    subscriptions: Mutex<Subscriptions>,
+    unacknowledged: Mutex<HashMap<(TopicId, u32), PendingPublish>>, // <(Topic ID, sequence number), DtgData>
+    events: broadcast::Sender<ConnectionEvent>,
+    datagrams: mpsc::Sender<Bytes>,
+    closing: AtomicBool,
+}
+
+impl RektClient {
+    /**
+     * Create a new client. Nothing is done on the network until `run` is called.
+     *
+     * @param endpoint: Endpoint, local endpoint with a default client config,
+     * @param server_addr: SocketAddr, address of the broker,
+     * @param server_name: &str, name expected in the broker certificate,
+     * @param token: &str, authentication token sent in the connect request, empty to connect anonymously,
+     * @param backoff: Backoff, reconnection policy,
+     *
+     * @return (Arc<RektClient>, mpsc::Receiver<Bytes>): the client and the receiver of every datagram sent by the broker
+     */
+    pub fn new(endpoint: Endpoint, server_addr: SocketAddr, server_name: &str, token: &str, backoff: Backoff) -> (Arc<RektClient>, mpsc::Receiver<Bytes>) {
+        let (events, _) = broadcast::channel(EVENT_CHANNEL_SIZE);
+        let (datagrams, receiver) = mpsc::channel(DATAGRAM_CHANNEL_SIZE);
+
+        let client = RektClient {
+            endpoint,
+            server_addr,
+            server_name: server_name.to_owned(),
+            token: token.to_owned(),
+            backoff,
+            connection: RwLock::new(None),
+            subscriptions: Mutex::new(Subscriptions::default()),
+            unacknowledged: Mutex::new(HashMap::default()),
+            events,
+            datagrams,
+            closing: AtomicBool::new(false),
+        };
+
+        (Arc::new(client), receiver)
+    }
+
+    /**
+     * Return a new receiver of connection events. Events raised before
+     * this call are not received.
+     */
+    pub fn events(&self) -> broadcast::Receiver<ConnectionEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connection.read().unwrap().is_some()
+    }
+
+    /**
+     * Send a datagram to the broker through the current connection.
+     *
+     * @param payload: Bytes, the datagram to send
+     *
+     * @return Result<(), ClientError>
+     */
+    pub fn send_datagram(&self, payload: Bytes) -> Result<(), ClientError> {
+        match &*self.connection.read().unwrap() {
+            Some(connection) => Ok(connection.send_datagram(payload)?),
+            None => Err(ClientError::NotConnected),
+        }
+    }
+
+    /**
+     * Publish a DtgData that the broker must acknowledge (QoS 1). It is sent
+     * again until its DtgDataAck is received, so the broker get it at least
+     * once, or until the broker refuse it. It is kept while the client is
+     * offline and sent once reconnected.
+     *
+     * @param topic_id: TopicId
+     * @param sequence_number: u32, identify the message with the topic, must not be reused while unacknowledged
+     * @param payload: Vec<u8>
+     *
+     * @return Result<(), ClientError>
+     */
+    pub fn publish_with_ack(&self, topic_id: TopicId, sequence_number: u32, payload: Vec<u8>) -> Result<(), ClientError> {
+        let datagram = Bytes::from(DtgData::new(sequence_number, topic_id, payload).with_ack().as_bytes());
+        let mut unacknowledged = self.unacknowledged.lock().unwrap();
+
+        let attempts = match self.send_datagram(datagram.clone()) {
+            Ok(()) => 1,
+            Err(ClientError::NotConnected) => 0,
+            Err(err) => return Err(err),
+        };
+        unacknowledged.insert((topic_id, sequence_number), PendingPublish { datagram, sent_at: Instant::now(), attempts });
+        Ok(())
+    }
+
+    /**
+     * @return usize, the amount of DtgData published with ack still waiting for their acknowledgement
+     */
+    pub fn unacknowledged_count(&self) -> usize {
+        self.unacknowledged.lock().unwrap().len()
+    }
+
+    /**
+     * Subscribe to a topic. The subscription is kept and replayed after each
+     * reconnection, so it is accepted even if the client is currently offline.
+     *
+     * @param topic_id: TopicId
+     * @param options: TopicOptions, TOPIC_OPTION_* flags, also replayed after each reconnection
+     *
+     * @return Result<(), ClientError>
+     */
+    pub fn subscribe_topic(&self, topic_id: TopicId, options: TopicOptions) -> Result<(), ClientError> {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        subscriptions.topics.insert(topic_id, options);
+        self.send_if_connected(DtgTopicRequest::new(TopicAction::Subscribe, topic_id, options).as_bytes())
+    }
+
+    /**
+     * Subscribe to a durable topic and ask for its messages from a sequence
+     * number, they are received through a stream. Only the subscription is
+     * replayed after a reconnection, not the resume. The broker send every
+     * message logged since the first one with this sequence number or a higher
+     * one : it only make sense for a topic with a single publisher.
+     *
+     * @param topic_id: TopicId
+     * @param options: TopicOptions, TOPIC_OPTION_* flags
+     * @param sequence_number: u32, the first sequence number to receive again
+     *
+     * @return Result<(), ClientError>
+     */
+    pub fn resume_topic(&self, topic_id: TopicId, options: TopicOptions, sequence_number: u32) -> Result<(), ClientError> {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        subscriptions.topics.insert(topic_id, options);
+        self.send_if_connected(DtgTopicRequest::new(TopicAction::Subscribe, topic_id, options).with_resume(sequence_number).as_bytes())
+    }
+
+    pub fn unsubscribe_topic(&self, topic_id: TopicId) -> Result<(), ClientError> {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        subscriptions.topics.remove(&topic_id);
+        self.send_if_connected(DtgTopicRequest::new(TopicAction::Unsubscribe, topic_id, TOPIC_OPTION_NONE).as_bytes())
+    }
+
+    /**
+     * Subscribe to an object. As for topics, the subscription is replayed
+     * after each reconnection.
+     *
+     * @param object_id: ObjectId
+     *
+     * @return Result<(), ClientError>
+     */
+    pub fn subscribe_object(&self, object_id: ObjectId) -> Result<(), ClientError> {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        subscriptions.objects.insert(object_id);
+        self.send_if_connected(DtgObjectRequest::new(ObjectRequestAction::Subscribe, object_id, HashSet::default()).as_bytes())
+    }
+
+    pub fn unsubscribe_object(&self, object_id: ObjectId) -> Result<(), ClientError> {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        subscriptions.objects.remove(&object_id);
+        self.send_if_connected(DtgObjectRequest::new(ObjectRequestAction::Unsubscribe, object_id, HashSet::default()).as_bytes())
+    }
+
+    /**
+     * Close the connection and stop the reconnection loop.
+     */
+    pub fn close(&self) {
+        self.closing.store(true, Ordering::Release);
+        if let Some(connection) = &*self.connection.read().unwrap() {
+            connection.close(VarInt::from_u32(0), b"Client closed");
+        }
+    }
+
+    /**
+     * Main loop of the client. It connect to the broker, replay subscriptions
+     * and forward every received datagram to the application. When the
+     * connection is lost it retry according to the backoff policy.
+     * It only return once `close` is called or when the backoff is exhausted.
+     *
+     * @return Result<(), ClientError>
+     */
+    pub async fn run(self: Arc<Self>) -> Result<(), ClientError> {
+        let mut attempt: u32 = 0;
+
+        while !self.closing.load(Ordering::Acquire) {
+            self.emit(ConnectionEvent::Connecting { attempt });
+
+            // A connection that cannot even be started is retried like an unreachable broker
+            let connecting = match self.endpoint.connect(self.server_addr, &self.server_name) {
+                Ok(connecting) => connecting.await.map_err(|err| err.to_string()),
+                Err(err) => Err(err.to_string()),
+            };
+
+            match connecting {
+                Ok(connection) => match self.handshake(&connection).await {
+                    Ok(peer_id) => {
+                        attempt = 0;
+                        info!("Connected to the broker {} with the id {}", connection.remote_address(), peer_id);
+                        *self.connection.write().unwrap() = Some(connection.clone());
+                        self.emit(ConnectionEvent::Connected { remote: connection.remote_address(), peer_id });
+
+                        if let Err(err) = self.restore_subscriptions(&connection) {
+                            warn!("Failed to restore subscriptions : {}", err);
+                        }
+
+                        let reason = tokio::select! {
+                            reason = self.receive_datagrams(&connection) => reason,
+                            reason = self.receive_streams(&connection) => reason,
+                            reason = self.retransmit_unacknowledged(&connection) => reason,
+                        };
+                        *self.connection.write().unwrap() = None;
+                        warn!("Connection to the broker lost : {}", reason);
+                        self.emit(ConnectionEvent::Disconnected { reason });
+                    }
+                    Err(reason) => {
+                        warn!("Connection refused by the broker : {}", reason);
+                        connection.close(VarInt::from_u32(0), b"Handshake failed");
+                        self.emit(ConnectionEvent::Disconnected { reason });
+                    }
+                },
+                Err(err) => {
+                    debug!("Connection attempt {} failed : {}", attempt, err);
+                }
+            }
+
+            if self.closing.load(Ordering::Acquire) {
+                break;
+            }
+
+            attempt += 1;
+            if self.backoff.is_exhausted(attempt) {
+                self.emit(ConnectionEvent::GaveUp { attempts: attempt });
+                return Err(ClientError::ReconnectionFailed(attempt));
+            }
+            let delay = self.backoff.delay(attempt);
+            info!("Reconnecting in {:?} (attempt {})", delay, attempt);
+            self.emit(ConnectionEvent::Reconnecting { attempt, delay });
+            tokio::time::sleep(delay).await;
+        }
+
+        self.emit(ConnectionEvent::Closed);
+        Ok(())
+    }
+
+    /**
+     * Send the connect request and wait for the broker answer. The broker
+     * ignore every other datagram until this handshake is done.
+     *
+     * @return Result<ClientId, String>, the id given by the broker or the reason of the refusal
+     */
+    async fn handshake(&self, connection: &Connection) -> Result<ClientId, String> {
+        connection.send_datagram(Bytes::from(DtgConnect::new(&self.token).as_bytes()))
+            .map_err(|err| err.to_string())?;
+
+        let answer = tokio::time::timeout(HANDSHAKE_TIMEOUT, async {
+            loop {
+                let datagram = match connection.read_datagram().await {
+                    Ok(datagram) => datagram,
+                    // A refused connection is closed with the DtgConnectNack as reason
+                    Err(ConnectionError::ApplicationClosed(close)) => return match DtgConnectNack::try_from(&close.reason[..]) {
+                        Ok(nack) => Err(String::from_utf8_lossy(&nack.payload).into_owned()),
+                        Err(_) => Err(format!("closed by the broker (code {})", close.error_code)),
+                    },
+                    Err(err) => return Err(err.to_string()),
+                };
+
+                match datagram.first().map(|code| DatagramType::from(*code)) {
+                    Some(DatagramType::ConnectAck) => {
+                        return DtgConnectAck::try_from(&datagram[..])
+                            .map(|ack| ack.peer_id)
+                            .map_err(str::to_string);
+                    }
+                    Some(DatagramType::ConnectNack) => {
+                        return match DtgConnectNack::try_from(&datagram[..]) {
+                            Ok(nack) => Err(String::from_utf8_lossy(&nack.payload).into_owned()),
+                            Err(err) => Err(err.to_string()),
+                        };
+                    }
+                    _ => debug!("Datagram received before the connect acknowledgement, ignored."),
+                }
+            }
+        }).await;
+
+        answer.unwrap_or_else(|_| Err("no answer to the connect request".to_string()))
+    }
+
+    /**
+     * Send every active subscription through a freshly opened connection.
+     */
+    fn restore_subscriptions(&self, connection: &Connection) -> Result<(), ClientError> {
+        let subscriptions = self.subscriptions.lock().unwrap();
+        for (topic_id, options) in &subscriptions.topics {
+            connection.send_datagram(Bytes::from(DtgTopicRequest::new(TopicAction::Subscribe, *topic_id, *options).as_bytes()))?;
+        }
+        for object_id in &subscriptions.objects {
+            connection.send_datagram(Bytes::from(DtgObjectRequest::new(ObjectRequestAction::Subscribe, *object_id, HashSet::default()).as_bytes()))?;
+        }
+
+        self.emit(ConnectionEvent::SubscriptionsRestored {
+            topics: subscriptions.topics.len(),
+            objects: subscriptions.objects.len(),
+        });
+        Ok(())
+    }
+
+    /**
+     * Forward datagrams to the application until the connection is closed.
+     * Heartbeat requests and pings are answered here and not forwarded.
+     *
+     * @return String, the reason of the disconnection
+     */
+    async fn receive_datagrams(&self, connection: &Connection) -> String {
+        loop {
+            let datagram = match connection.read_datagram().await {
+                Ok(datagram) => datagram,
+                Err(err) => return err.to_string(),
+            };
+
+            let answer = match datagram.first().map(|code| DatagramType::from(*code)) {
+                Some(DatagramType::HeartbeatRequest) => DtgHeartbeat::new().as_bytes(),
+                // A refused DtgData is dropped as well : sending it again would be refused too
+                Some(DatagramType::DataAck | DatagramType::DataNack) => {
+                    match DtgDataAck::try_from(&datagram[..]) {
+                        Ok(ack) => {
+                            self.unacknowledged.lock().unwrap().remove(&(ack.topic_id, ack.sequence_number));
+                            if ack.is_nack() {
+                                warn!("Sequence {} of topic {} refused by the broker.", ack.sequence_number, ack.topic_id);
+                            }
+                        }
+                        Err(err) => warn!("Invalid data acknowledgement received : {}", err),
+                    }
+                    continue;
+                }
+                Some(DatagramType::Ping) => match DtgPing::try_from(&datagram[..]) {
+                    Ok(ping) => DtgPong::new(ping.ping_id).as_bytes(),
+                    Err(err) => {
+                        warn!("Invalid ping received : {}", err);
+                        continue;
+                    }
+                },
+                _ => {
+                    // The application may have dropped the receiver, datagrams are then discarded.
+                    let _ = self.datagrams.send(datagram).await;
+                    continue;
+                }
+            };
+            if let Err(err) = connection.send_datagram(Bytes::from(answer)) {
+                warn!("Failed to answer the broker : {}", err);
+            }
+        }
+    }
+
+    /**
+     * Accept the streams opened by the broker until the connection is closed.
+     * Each stream is read in its own task. The bidirectional stream carry
+     * the topics delivered in order, for the whole connection.
+     *
+     * @return String, the reason of the disconnection
+     */
+    async fn receive_streams(&self, connection: &Connection) -> String {
+        loop {
+            tokio::select! {
+                stream = connection.accept_uni() => match stream {
+                    Ok(receiver) => {
+                        tokio::spawn(read_data_stream(receiver, self.datagrams.clone(), "topic history"));
+                    }
+                    Err(err) => return err.to_string(),
+                },
+                stream = connection.accept_bi() => match stream {
+                    // Nothing is sent back to the broker through this stream
+                    Ok((_sender, receiver)) => {
+                        tokio::spawn(read_data_stream(receiver, self.datagrams.clone(), "ordered stream"));
+                    }
+                    Err(err) => return err.to_string(),
+                },
+            }
+        }
+    }
+
+    /**
+     * Send again the DtgData published with ack that were not acknowledged
+     * in time, until the connection is closed. They are given up after
+     * MAX_PUBLISH_ATTEMPTS and a PublishFailed event is raised.
+     *
+     * @return String, never returns while the connection is open
+     */
+    async fn retransmit_unacknowledged(&self, connection: &Connection) -> String {
+        let mut interval = tokio::time::interval(RETRANSMIT_PERIOD);
+        loop {
+            interval.tick().await;
+
+            let mut failed = Vec::new();
+            self.unacknowledged.lock().unwrap().retain(|(topic_id, sequence_number), publish| {
+                if publish.attempts > 0 && publish.sent_at.elapsed() < ACK_TIMEOUT {
+                    return true;
+                }
+                if publish.attempts >= MAX_PUBLISH_ATTEMPTS {
+                    failed.push((*topic_id, *sequence_number));
+                    return false;
+                }
+                if let Err(err) = connection.send_datagram(publish.datagram.clone()) {
+                    debug!("Failed to retransmit the sequence {} of topic {} : {}", sequence_number, topic_id, err);
+                }
+                publish.attempts += 1;
+                publish.sent_at = Instant::now();
+                true
+            });
+
+            for (topic_id, sequence_number) in failed {
+                warn!("Sequence {} of topic {} never acknowledged after {} attempts, given up", sequence_number, topic_id, MAX_PUBLISH_ATTEMPTS);
+                self.emit(ConnectionEvent::PublishFailed { topic_id, sequence_number });
+            }
+        }
+    }
+
+    fn send_if_connected(&self, datagram: Vec<u8>) -> Result<(), ClientError> {
+        match self.send_datagram(Bytes::from(datagram)) {
+            Err(ClientError::NotConnected) => Ok(()), // will be sent on the next connection
+            result => result,
+        }
+    }
+
+    fn emit(&self, event: ConnectionEvent) {
+        // An error only means that nobody is listening.
+        let _ = self.events.send(event);
+    }
+}
+
+/**
+ * Read a stream of DtgData written one after the other by the broker : a topic
+ * history, durable messages replayed by the broker, a message of a reliable
+ * unordered topic or the bidirectional stream carrying the topics delivered in
+ * order, open for the whole connection. Each DtgData is forwarded to the
+ * application as soon as it is complete, as if it was received as a datagram,
+ * so a stream of any length is read without keeping it whole in memory.
+ *
+ * @param receiver: RecvStream
+ * @param datagrams: mpsc::Sender<Bytes>, the channel of the application
+ * @param name: &str, the kind of stream, displayed in the logs
+ */
+async fn read_data_stream(mut receiver: RecvStream, datagrams: mpsc::Sender<Bytes>, name: &str) {
+    let mut buffer = BytesMut::new();
+
+    loop {
+        match receiver.read_chunk(MAX_CHUNK_SIZE, true).await {
+            Ok(Some(chunk)) => buffer.extend_from_slice(&chunk.bytes),
+            Ok(None) => break, // The stream is finished
+            Err(err) => {
+                warn!("Failed to read the {} : {}", name, err);
+                return;
+            }
+        }
+
+        // An error only means that the next DtgData is not complete yet
+        while let Ok(data) = DtgData::try_from(&buffer[..]) {
+            let size = DtgData::get_default_byte_size() + data.size as usize;
+            // The application may have dropped the receiver, datagrams are then discarded.
+            let _ = datagrams.send(buffer.split_to(size).freeze()).await;
+        }
+    }
+
+    if !buffer.is_empty() {
+        warn!("The {} ended with {} bytes of an incomplete DtgData, they are ignored.", name, buffer.len());
+    }
+}
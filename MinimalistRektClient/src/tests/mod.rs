@@ -0,0 +1,2 @@
+#[cfg(test)]
+mod unit_test;
\ No newline at end of file
@@ -0,0 +1,81 @@
+#![allow(non_snake_case)]
+
+use std::time::Duration;
+
+use crate::backoff::Backoff;
+use crate::tls::parse_fingerprint;
+
+// ------------------------------------------------
+//    TLS test
+// ------------------------------------------------
+
+#[test]
+fn test_parse_fingerprint() {
+    let hex = "00112233445566778899aabbccddeeff00112233445566778899AABBCCDDEEFF";
+    let fingerprint = parse_fingerprint(hex).unwrap();
+    assert_eq!(fingerprint[0], 0x00);
+    assert_eq!(fingerprint[1], 0x11);
+    assert_eq!(fingerprint[31], 0xFF);
+
+    // Bytes separated by ':' as displayed by openssl
+    let separated: Vec<String> = hex.as_bytes().chunks(2).map(|byte| String::from_utf8_lossy(byte).to_string()).collect();
+    assert_eq!(parse_fingerprint(&separated.join(":")).unwrap(), fingerprint);
+}
+
+#[test]
+fn test_parse_fingerprint_invalid() {
+    assert!(parse_fingerprint("").is_err());
+    assert!(parse_fingerprint(&"0".repeat(63)).is_err());
+    assert!(parse_fingerprint(&"g".repeat(64)).is_err());
+    // 64 bytes but not ASCII : must be refused, not panic on a char boundary
+    assert!(parse_fingerprint(&format!("0é{}", "0".repeat(61))).is_err());
+}
+
+// ------------------------------------------------
+//    Backoff test
+// ------------------------------------------------
+
+fn backoff_without_jitter() -> Backoff {
+    Backoff {
+        initial_delay: Duration::from_millis(100),
+        max_delay: Duration::from_secs(1),
+        multiplier: 2.0,
+        jitter: 0.0,
+        max_attempts: Some(5),
+    }
+}
+
+#[test]
+fn test_Backoff_delay() {
+    let backoff = backoff_without_jitter();
+    assert_eq!(backoff.delay(0), Duration::from_millis(100));
+    assert_eq!(backoff.delay(1), Duration::from_millis(100));
+    assert_eq!(backoff.delay(2), Duration::from_millis(200));
+    assert_eq!(backoff.delay(4), Duration::from_millis(800));
+    // Capped by max_delay, even when the exponent is huge
+    assert_eq!(backoff.delay(5), Duration::from_secs(1));
+    assert_eq!(backoff.delay(u32::MAX), Duration::from_secs(1));
+}
+
+#[test]
+fn test_Backoff_delay_jitter() {
+    let backoff = Backoff { jitter: 0.5, ..backoff_without_jitter() };
+    for _ in 0..100 {
+        let delay = backoff.delay(3);
+        assert!(delay >= Duration::from_millis(200) && delay <= Duration::from_millis(400), "{:?}", delay);
+    }
+
+    // A jitter out of bounds is clamped : the delay is never negative
+    let backoff = Backoff { jitter: 5.0, ..backoff_without_jitter() };
+    for _ in 0..100 {
+        assert!(backoff.delay(3) <= Duration::from_millis(400));
+    }
+}
+
+#[test]
+fn test_Backoff_is_exhausted() {
+    let backoff = backoff_without_jitter();
+    assert!(!backoff.is_exhausted(4));
+    assert!(backoff.is_exhausted(5));
+    assert!(!Backoff::default().is_exhausted(u32::MAX));
+}
@@ -0,0 +1,181 @@
+// This document contain the TLS configuration of the client. The broker
+// certificate can be verified against the public CA roots, against a custom
+// trust store file (private CA) or pinned by its SHA-256 fingerprint.
+// Skipping the verification is still possible for local development but it
+// must be explicitly asked with TlsVerification::Insecure.
+
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use log::warn;
+use rustls::{Certificate, ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName};
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use sha2::{Digest, Sha256};
+
+pub type Fingerprint = [u8; 32];
+
+/**
+ * TlsVerification are all the way the client
+ * can use to trust the broker certificate.
+ */
+#[derive(Debug, Clone, Default)]
+pub enum TlsVerification {
+    #[default]
+    WebPkiRoots, // Public CA roots (Mozilla bundle)
+    TrustStore(PathBuf), // PEM file containing the trusted CA certificates
+    Pinned(Vec<Fingerprint>), // SHA-256 fingerprints of the accepted certificates
+    Insecure, // Accept everything. Local development only !
+}
+
+/**
+ * This function build the rustls client configuration
+ * according to the given verification mode.
+ *
+ * @param verification: &TlsVerification
+ *
+ * @return Result<ClientConfig, Box<dyn Error>>
+ */
+pub fn build_client_crypto(verification: &TlsVerification) -> Result<ClientConfig, Box<dyn Error>> {
+    let builder = ClientConfig::builder().with_safe_defaults();
+
+    let crypto = match verification {
+        TlsVerification::WebPkiRoots => {
+            let mut roots = RootCertStore::empty();
+            roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+                OwnedTrustAnchor::from_subject_spki_name_constraints(
+                    anchor.subject,
+                    anchor.spki,
+                    anchor.name_constraints,
+                )
+            }));
+            builder.with_root_certificates(roots).with_no_client_auth()
+        }
+        TlsVerification::TrustStore(path) => {
+            builder.with_root_certificates(load_trust_store(path)?).with_no_client_auth()
+        }
+        TlsVerification::Pinned(fingerprints) => {
+            if fingerprints.is_empty() {
+                return Err("Certificate pinning needs at least one fingerprint.".into());
+            }
+            builder.with_custom_certificate_verifier(PinnedServerVerification::new(fingerprints.clone()))
+                .with_no_client_auth()
+        }
+        TlsVerification::Insecure => {
+            warn!("Server certificate verification is DISABLED. Never use this mode outside of local development.");
+            builder.with_custom_certificate_verifier(SkipServerVerification::new())
+                .with_no_client_auth()
+        }
+    };
+
+    Ok(crypto)
+}
+
+/**
+ * This function read every certificate of a PEM file
+ * and return them as a root store.
+ *
+ * @param path: &Path, the trust store file
+ *
+ * @return Result<RootCertStore, Box<dyn Error>>
+ */
+pub fn load_trust_store(path: &Path) -> Result<RootCertStore, Box<dyn Error>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let certificates = rustls_pemfile::certs(&mut reader)?;
+
+    let mut roots = RootCertStore::empty();
+    let (added, ignored) = roots.add_parsable_certificates(&certificates);
+    if ignored > 0 {
+        warn!("{} certificate(s) of the trust store {} are invalid and have been ignored.", ignored, path.display());
+    }
+    if added == 0 {
+        return Err(format!("No valid certificate found in the trust store {}", path.display()).into());
+    }
+
+    Ok(roots)
+}
+
+/**
+ * This function parse a SHA-256 fingerprint written in hexadecimal.
+ * Bytes can be separated by ':' as displayed by openssl.
+ *
+ * @param value: &str, ex: "AB:CD:..." or "abcd..."
+ *
+ * @return Result<Fingerprint, Box<dyn Error>>
+ */
+pub fn parse_fingerprint(value: &str) -> Result<Fingerprint, Box<dyn Error>> {
+    let hex: String = value.chars().filter(|c| *c != ':').collect();
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Err(format!("Invalid SHA-256 fingerprint length for {}", value).into());
+    }
+
+    let mut fingerprint: Fingerprint = [0; 32];
+    for (i, byte) in fingerprint.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)?;
+    }
+
+    Ok(fingerprint)
+}
+
+pub fn fingerprint_of(certificate: &Certificate) -> Fingerprint {
+    Sha256::digest(&certificate.0).into()
+}
+
+
+// Implementation of `ServerCertVerifier` that only trust certificates with a known fingerprint.
+// The handshake signatures are still verified by the default methods of the trait.
+struct PinnedServerVerification {
+    fingerprints: Vec<Fingerprint>,
+}
+
+impl PinnedServerVerification {
+    fn new(fingerprints: Vec<Fingerprint>) -> Arc<Self> {
+        Arc::new(Self { fingerprints })
+    }
+}
+
+impl ServerCertVerifier for PinnedServerVerification {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item=&[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let fingerprint = fingerprint_of(end_entity);
+        if self.fingerprints.contains(&fingerprint) {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General("Server certificate fingerprint doesn't match any pinned fingerprint".to_string()))
+        }
+    }
+}
+
+
+// Implementation of `ServerCertVerifier` that verifies everything as trustworthy.
+struct SkipServerVerification;
+
+impl SkipServerVerification {
+    fn new() -> Arc<Self> {
+        Arc::new(Self)
+    }
+}
+
+impl ServerCertVerifier for SkipServerVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item=&[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
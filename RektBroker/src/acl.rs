@@ -0,0 +1,182 @@
+// This document contain the access control of the broker. The ACL file set in
+// the [auth] table of the config grant publish and subscribe rights by identity
+// and topic pattern. The rights of a client are resolved once when it connects,
+// from its own rules and the ones of the identity "*". Anything not granted is
+// denied. Without ACL file, every client can publish and subscribe to any topic.
+
+use std::fs;
+
+use rekt_lib::libs::types::TopicId;
+use serde::Deserialize;
+
+use crate::CONFIG;
+use crate::prelude::{Error, Result};
+
+const ANY_IDENTITY: &str = "*";
+
+// Content of the ACL file
+#[derive(Deserialize, Debug)]
+struct AclFile {
+    rules: Option<Vec<AclRule>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AclRule {
+    identity: String,
+    publish: Option<Vec<String>>,
+    subscribe: Option<Vec<String>>,
+}
+
+/**
+ * A TopicPattern match a set of topics : "*" every topic,
+ * "42" a single topic and "100-199" an inclusive range.
+ */
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TopicPattern {
+    Any,
+    Range(TopicId, TopicId),
+}
+
+impl TopicPattern {
+    pub fn matches(&self, topic_id: TopicId) -> bool {
+        match self {
+            TopicPattern::Any => true,
+            TopicPattern::Range(first, last) => (*first..=*last).contains(&topic_id),
+        }
+    }
+
+    /**
+     * This method parse a topic pattern of the ACL file or of the config.
+     *
+     * @param pattern: &str, "*", "42", "100-199" or "1000-*"
+     *
+     * @return Option<TopicPattern>, None if the pattern is invalid
+     */
+    pub fn parse(pattern: &str) -> Option<TopicPattern> {
+        let pattern = pattern.trim();
+        if pattern == "*" {
+            return Some(TopicPattern::Any);
+        }
+
+        match pattern.split_once('-') {
+            Some((first, "*")) => Some(TopicPattern::Range(first.trim().parse().ok()?, TopicId::MAX)),
+            Some((first, last)) => {
+                let (first, last): (TopicId, TopicId) = (first.trim().parse().ok()?, last.trim().parse().ok()?);
+                (first <= last).then_some(TopicPattern::Range(first, last))
+            }
+            None => pattern.parse().ok().map(|topic_id| TopicPattern::Range(topic_id, topic_id)),
+        }
+    }
+}
+
+/**
+ * Permissions are the rights of one client, attached to it when it connects.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct Permissions {
+    publish: Vec<TopicPattern>,
+    subscribe: Vec<TopicPattern>,
+}
+
+impl Permissions {
+    pub fn allow_all() -> Permissions {
+        Permissions {
+            publish: vec![TopicPattern::Any],
+            subscribe: vec![TopicPattern::Any],
+        }
+    }
+
+    pub fn new(publish: Vec<TopicPattern>, subscribe: Vec<TopicPattern>) -> Permissions {
+        Permissions { publish, subscribe }
+    }
+
+    pub fn can_publish(&self, topic_id: TopicId) -> bool {
+        self.publish.iter().any(|pattern| pattern.matches(topic_id))
+    }
+
+    pub fn can_subscribe(&self, topic_id: TopicId) -> bool {
+        self.subscribe.iter().any(|pattern| pattern.matches(topic_id))
+    }
+}
+
+pub struct AccessControl {
+    enabled: bool,
+    rules: Vec<(String, Permissions)>, // <identity, rights granted by the rule>
+}
+
+impl AccessControl {
+    /**
+     * This method build the access control from the config. If the ACL
+     * file can't be read, the access control stay enabled and deny everything.
+     *
+     * @return AccessControl
+     */
+    pub fn from_config() -> AccessControl {
+        let path = match &CONFIG.auth_acl_file {
+            Some(path) => path,
+            None => {
+                warn!("- No ACL file set, every client can publish and subscribe to any topic.");
+                return AccessControl { enabled: false, rules: Vec::new() };
+            }
+        };
+
+        match AccessControl::load(path) {
+            Ok(rules) => {
+                info!("- {} ACL rules loaded from {}", rules.len(), path);
+                AccessControl { enabled: true, rules }
+            }
+            Err(err) => {
+                error!("- Failed to load the ACL file, every publish and subscribe request will be denied : {}", err);
+                AccessControl { enabled: true, rules: Vec::new() }
+            }
+        }
+    }
+
+    fn load(path: &str) -> Result<Vec<(String, Permissions)>> {
+        let content = fs::read_to_string(path)?;
+        let file: AclFile = toml::from_str(&content)
+            .map_err(|err| Error::Auth(format!("Invalid ACL file {} : {}", path, err)))?;
+
+        let parse_patterns = |identity: &str, patterns: Option<Vec<String>>| -> Result<Vec<TopicPattern>> {
+            patterns.unwrap_or_default()
+                .iter()
+                .map(|pattern| TopicPattern::parse(pattern).ok_or_else(|| {
+                    Error::Auth(format!("Invalid topic pattern \"{}\" for the identity {}", pattern, identity))
+                }))
+                .collect()
+        };
+
+        file.rules.unwrap_or_default()
+            .into_iter()
+            .map(|rule| {
+                let permissions = Permissions::new(
+                    parse_patterns(&rule.identity, rule.publish)?,
+                    parse_patterns(&rule.identity, rule.subscribe)?,
+                );
+                Ok((rule.identity, permissions))
+            })
+            .collect()
+    }
+
+    /**
+     * This method resolve the rights of an identity from every matching rule.
+     *
+     * @param identity: &str, the identity given by the authenticator
+     *
+     * @return Permissions
+     */
+    pub fn permissions_of(&self, identity: &str) -> Permissions {
+        if !self.enabled {
+            return Permissions::allow_all();
+        }
+
+        let mut permissions = Permissions::default();
+        for (rule_identity, granted) in &self.rules {
+            if rule_identity == identity || rule_identity == ANY_IDENTITY {
+                permissions.publish.extend_from_slice(&granted.publish);
+                permissions.subscribe.extend_from_slice(&granted.subscribe);
+            }
+        }
+        permissions
+    }
+}
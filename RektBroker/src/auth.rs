@@ -0,0 +1,117 @@
+// This document contain the authenticator of the broker. Clients put a token in
+// their DtgConnect, the broker hash it and look for the matching identity in the
+// credentials file set in the [auth] table of the config. Only the SHA-256 of
+// each token is stored in this file, ex : `echo -n "my-token" | sha256sum`.
+// Without credentials file, the authentication is disabled and every client is
+// connected as anonymous.
+
+use std::collections::HashMap;
+use std::fs;
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::CONFIG;
+use crate::prelude::{Error, Result};
+
+pub const ANONYMOUS_IDENTITY: &str = "anonymous";
+
+type TokenHash = [u8; 32];
+
+// Content of the credentials file
+#[derive(Deserialize, Debug)]
+struct CredentialsFile {
+    clients: Option<Vec<Credentials>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Credentials {
+    identity: String,
+    token_sha256: String,
+}
+
+pub struct Authenticator {
+    enabled: bool,
+    identities: HashMap<TokenHash, String>, // <SHA-256 of the token, identity>
+}
+
+impl Authenticator {
+    /**
+     * This method build the authenticator from the config. If the credentials
+     * file can't be read, the authentication stay enabled and every client is refused.
+     *
+     * @return Authenticator
+     */
+    pub fn from_config() -> Authenticator {
+        let path = match &CONFIG.auth_credentials_file {
+            Some(path) => path,
+            None => {
+                warn!("- No credentials file set, authentication is DISABLED : every client is connected as {}.", ANONYMOUS_IDENTITY);
+                return Authenticator { enabled: false, identities: HashMap::default() };
+            }
+        };
+
+        match Authenticator::load(path) {
+            Ok(identities) => {
+                info!("- {} client identities loaded from {}", identities.len(), path);
+                Authenticator { enabled: true, identities }
+            }
+            Err(err) => {
+                error!("- Failed to load the credentials file, every connection will be refused : {}", err);
+                Authenticator { enabled: true, identities: HashMap::default() }
+            }
+        }
+    }
+
+    fn load(path: &str) -> Result<HashMap<TokenHash, String>> {
+        let content = fs::read_to_string(path)?;
+        let file: CredentialsFile = toml::from_str(&content)
+            .map_err(|err| Error::Auth(format!("Invalid credentials file {} : {}", path, err)))?;
+
+        let mut identities: HashMap<TokenHash, String> = HashMap::default();
+        for credentials in file.clients.unwrap_or_default() {
+            let hash = parse_token_hash(&credentials.token_sha256).ok_or_else(|| {
+                Error::Auth(format!("Invalid token_sha256 for the identity {}", credentials.identity))
+            })?;
+            if identities.insert(hash, credentials.identity.clone()).is_some() {
+                return Err(Error::Auth(format!("The token of {} is used by another identity.", credentials.identity)));
+            }
+        }
+        Ok(identities)
+    }
+
+    /**
+     * This method return the identity matching a token.
+     *
+     * @param token: &[u8], the payload of the DtgConnect
+     *
+     * @return core::result::Result<String, &str>, the identity or the reason of the failure
+     */
+    pub fn authenticate(&self, token: &[u8]) -> core::result::Result<String, &'static str> {
+        if !self.enabled {
+            return Ok(ANONYMOUS_IDENTITY.to_string());
+        }
+        if token.is_empty() {
+            return Err("Authentication required : no token provided.");
+        }
+
+        let hash: TokenHash = Sha256::digest(token).into();
+        self.identities.get(&hash)
+            .cloned()
+            .ok_or("Authentication failed : invalid token.")
+    }
+}
+
+// Decode a SHA-256 written in hexadecimal, as printed by sha256sum
+fn parse_token_hash(hex: &str) -> Option<TokenHash> {
+    let hex = hex.trim();
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+
+    let mut hash: TokenHash = [0; 32];
+    for (i, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(hash)
+}
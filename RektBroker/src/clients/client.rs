@@ -1,11 +1,23 @@
 use std::fmt::{Display, Formatter};
 use std::net::IpAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use bytes::Bytes;
 use quinn::Connection;
 use rand::random;
+use rekt_lib::enums::delivery_mode::DeliveryMode;
 
-use crate::prelude::ClientId;
+use crate::ACCESS_CONTROL;
+use crate::acl::Permissions;
+use crate::clients::dedup_window::DedupWindow;
+use crate::clients::egress::{EgressQueue, run_egress, TopicPriority};
+use crate::clients::latency::Latency;
+use crate::clients::rate_limiter::{RateLimit, RateLimiter};
+use crate::job_system::OverflowCounters;
+use crate::prelude::{ClientId, Result};
 use crate::streams::streams::{RBiStream, RUnreliableStream};
 
 #[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
@@ -41,20 +53,131 @@ impl Display for ConnectionId {
 pub struct Client {
     pub id: ClientId,
     pub connection_id: ConnectionId,
+    pub identity: String, // Given by the authenticator
+    pub permissions: Permissions, // Rights of the identity, resolved once from the ACL
     pub unreliable_stream: RUnreliableStream,
-    pub bidirectional_stream: RBiStream,
+    pub egress: Arc<EgressQueue>, // DtgData waiting to be sent, by priority class
+    pub protocol_errors: u32, // Amount of invalid or unexpected datagrams received
+    pub dedup_window: DedupWindow, // Last sequence numbers published with ack, per topic
+    pub stats: Arc<ClientStats>, // Shared with the receive loops of the connection
+}
+
+/**
+ * ClientStats count the packets of a client queued in the
+ * job system and the ones dropped when the buffer was full.
+ * They also keep the time of the last datagram received
+ * and the latency measured by the ping sender.
+ * The rate limiter filter the DtgData of the client
+ * before they reach the job system.
+ */
+#[derive(Debug)]
+pub struct ClientStats {
+    pub queued_packets: AtomicUsize,
+    pub overflows: OverflowCounters,
+    pub latency: Latency,
+    pub rate_limiter: RateLimiter,
+    last_activity: AtomicU64, // ms since UNIX_EPOCH
+}
+
+impl ClientStats {
+    pub fn new(rate_limit: RateLimit) -> ClientStats {
+        ClientStats {
+            queued_packets: AtomicUsize::new(0),
+            overflows: OverflowCounters::default(),
+            latency: Latency::default(),
+            rate_limiter: RateLimiter::new(rate_limit),
+            last_activity: AtomicU64::new(now_ms()),
+        }
+    }
+
+    /**
+     * This method record that the client just sent something.
+     */
+    pub fn touch(&self) {
+        self.last_activity.store(now_ms(), Ordering::Relaxed);
+    }
+
+    /**
+     * @return Duration, the time elapsed since the last activity of the client
+     */
+    pub fn idle_time(&self) -> Duration {
+        Duration::from_millis(now_ms().saturating_sub(self.last_activity.load(Ordering::Relaxed)))
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0)
 }
 
 impl Client {
-    pub fn new(connection_id: ConnectionId, connection: Connection, bi_stream: RBiStream) -> Client
+    pub fn new(connection_id: ConnectionId, connection: Connection, bi_stream: RBiStream, identity: String) -> Client
     {
+        // The bidirectional stream is written by the egress task only
+        let egress = Arc::new(EgressQueue::default());
+        tokio::spawn(run_egress(connection_id, connection.clone(), bi_stream, egress.clone()));
+
         Client {
             id: Client::get_new_id(),
             connection_id,
+            permissions: ACCESS_CONTROL.permissions_of(&identity),
+            stats: Arc::new(ClientStats::new(RateLimit::of(&identity))),
+            identity,
             unreliable_stream: RUnreliableStream::from_connection(connection),
-            bidirectional_stream: bi_stream,
+            egress,
+            protocol_errors: 0,
+            dedup_window: DedupWindow::default(),
         }
     }
+
+    /**
+     * A client reconnecting from the same address get the same ConnectionId : the
+     * stable id of its QUIC connection tell the new connection from the old one.
+     *
+     * @return usize
+     */
+    pub fn stable_id(&self) -> usize {
+        self.unreliable_stream.stream.stable_id()
+    }
+
+    /**
+     * This methods send a datagram to the client through the unreliable stream.
+     *
+     * @param datagram: Vec<u8>, the datagram as bytes
+     *
+     * @return Result<()>
+     */
+    pub fn send_datagram(&self, datagram: Vec<u8>) -> Result<()> {
+        self.send_bytes(Bytes::from(datagram))
+    }
+
+    /**
+     * Same as send_datagram but for datagrams already stored as Bytes.
+     * It is used to forward a datagram to many clients without copying it.
+     *
+     * @param datagram: Bytes, the datagram
+     *
+     * @return Result<()>
+     */
+    pub fn send_bytes(&self, datagram: Bytes) -> Result<()> {
+        self.unreliable_stream.stream.send_datagram(datagram)?;
+        Ok(())
+    }
+
+    /**
+     * This methods forward a DtgData to the client with the delivery mode
+     * of its subscription. It is queued in the egress queue of its topic
+     * priority class and sent by the egress task of the client.
+     *
+     * @param datagram: Bytes, the DtgData
+     * @param delivery_mode: DeliveryMode
+     * @param priority: TopicPriority
+     */
+    pub fn send_data(&self, datagram: Bytes, delivery_mode: DeliveryMode, priority: TopicPriority) {
+        self.egress.push(datagram, delivery_mode, priority);
+    }
     /**
      * This methods return a unique id for a new client.
      *
@@ -72,5 +195,23 @@ impl Client {
 #[derive(Debug)]
 pub struct Packet {
     pub source: ConnectionId,
-    pub datagram: [u8; 1024],
-}
\ No newline at end of file
+    pub datagram: Bytes,
+    pub len: usize,
+    pub stats: Arc<ClientStats>, // Stats of the source client
+}
+
+impl Packet {
+    pub fn new(source: ConnectionId, datagram: Bytes, stats: Arc<ClientStats>) -> Packet
+    {
+        Packet {
+            source,
+            len: datagram.len(),
+            datagram,
+            stats,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.datagram[..self.len]
+    }
+}
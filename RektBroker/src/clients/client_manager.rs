@@ -0,0 +1,318 @@
+// This document contain the client manager. A new connection stay pending until
+// the client send its DtgConnect : its token is checked by the authenticator and
+// it is then admitted, or refused with a DtgConnectNack. Each admitted connection run receive loops that read every
+// QUIC datagram and every stream opened by the client, turn them into packets
+// and give them to the job system. The DtgData exceeding the rate limit of the
+// client are dropped here, before they take room in the packet buffer. When the connection is closed, the client
+// and all of its resources are released.
+
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use bytes::Bytes;
+use quinn::{Connection, ConnectionError, RecvStream, VarInt};
+use tokio::time::timeout;
+use rekt_lib::datagrams::connect_requests::{DtgConnect, DtgConnectNack};
+use rekt_lib::datagrams::shutdown_request::DtgShutdown;
+use rekt_lib::enums::datagram_type::DatagramType;
+use rekt_lib::enums::end_connection_reason::EndConnexionReason;
+
+use crate::{ADMITTED_CLIENTS, AUTHENTICATOR, CLIENT_MAP, CONFIG, job_system, OBJECT_REGISTRY, PACKET_POOL, TOPIC_REGISTRY};
+use crate::clients::client::{Client, ClientStats, ConnectionId, Packet};
+use crate::clients::rate_limiter::RateVerdict;
+
+/**
+ * This method wait for the DtgConnect of a new connection, authenticate its
+ * token and reserve a slot for the client. Refused connections receive a DtgConnectNack and
+ * are closed. Datagrams received before the DtgConnect are ignored. The reserved slot
+ * is released by remove_client, or by release_slot if the client is never stored.
+ *
+ * @param connection_id: ConnectionId, the pending client
+ * @param connection: &Connection, its QUIC connection
+ *
+ * @return Option<String>, the identity of the client if it is admitted
+ */
+pub async fn handshake(connection_id: ConnectionId, connection: &Connection) -> Option<String> {
+    let handshake_timeout = Duration::from_secs(CONFIG.handshake_timeout.into());
+
+    let refusal = match timeout(handshake_timeout, wait_connect(connection_id, connection)).await {
+        Err(_) => (EndConnexionReason::TimeOut, "Handshake timeout : no connect request received.".to_string()),
+        Ok(Err(err)) => {
+            if CONFIG.debug_client_manager {
+                debug!("Connection with {} closed during the handshake : {}", connection_id, err);
+            }
+            return None;
+        }
+        Ok(Ok(Err(reason))) => (EndConnexionReason::Unknown, reason),
+        Ok(Ok(Ok(connect))) => match AUTHENTICATOR.authenticate(&connect.payload) {
+            Err(reason) => (EndConnexionReason::Unauthorized, reason.to_string()),
+            Ok(identity) if reserve_slot() => {
+                return Some(identity);
+            }
+            Ok(_) => (EndConnexionReason::BrokerFull, format!("The broker is full ({} clients).", CONFIG.max_clients)),
+        }
+    };
+
+    let (reason, message) = refusal;
+    warn!("Connection of {} refused : {}", connection_id, message);
+    let nack = DtgConnectNack::new(&message).as_bytes();
+    let _ = connection.send_datagram(Bytes::from(nack.clone())); // best effort, the close reason carry it anyway
+    connection.close(VarInt::from(u8::from(reason)), &nack);
+    None
+}
+
+/**
+ * This method reserve a slot for a new client. The count is checked and
+ * incremented at once, so concurrent handshakes can't exceed max_clients.
+ *
+ * @return bool, false if the broker is full
+ */
+fn reserve_slot() -> bool {
+    ADMITTED_CLIENTS
+        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+            (CONFIG.max_clients == 0 || count < CONFIG.max_clients).then_some(count + 1)
+        })
+        .is_ok()
+}
+
+// Release the slot of a client admitted by the handshake but no longer stored
+pub fn release_slot() {
+    ADMITTED_CLIENTS.fetch_sub(1, Ordering::AcqRel);
+}
+
+/**
+ * @return Result<Result<DtgConnect, String>, ConnectionError>, the outer error if the connection
+ * is closed, the inner one if the DtgConnect can't be decoded
+ */
+async fn wait_connect(connection_id: ConnectionId, connection: &Connection) -> Result<Result<DtgConnect, String>, ConnectionError> {
+    loop {
+        let datagram = connection.read_datagram().await?;
+
+        if datagram.first().map(|code| DatagramType::from(*code)) == Some(DatagramType::Connect) {
+            return Ok(DtgConnect::try_from(&datagram[..]).map_err(str::to_string));
+        }
+        warn!("Datagram received from {} before its connect request, ignored.", connection_id);
+    }
+}
+
+/**
+ * This method run the receive loops of a connection. It
+ * return once the connection is closed and the client removed.
+ *
+ * @param connection_id: ConnectionId, the client identifier
+ * @param connection: Connection, the QUIC connection of the client
+ * @param stats: Arc<ClientStats>, the stats of the client, given to each packet
+ */
+pub async fn listen_connection(connection_id: ConnectionId, connection: Connection, stats: Arc<ClientStats>) {
+    let reason = tokio::select! {
+        reason = receive_datagrams(connection_id, &connection, &stats) => reason,
+        reason = receive_uni_streams(connection_id, &connection, &stats) => reason,
+        reason = receive_bi_streams(connection_id, &connection, &stats) => reason,
+    };
+
+    info!("Connection with {} closed : {}", connection_id, reason);
+    remove_client(connection_id, connection.stable_id());
+}
+
+/**
+ * This method close the connection of a client on the broker initiative. The
+ * DtgShutdown is sent as a datagram and also given as the close reason, since
+ * pending datagrams may be discarded once the connection is closed.
+ *
+ * @param connection_id: ConnectionId, the client to disconnect
+ * @param stable_id: usize, the stable id of its connection
+ * @param reason: EndConnexionReason
+ */
+pub fn disconnect_client(connection_id: ConnectionId, stable_id: usize, reason: EndConnexionReason) {
+    if let Some(client) = CLIENT_MAP.get(&connection_id).filter(|client| client.stable_id() == stable_id) {
+        close_client(&client, reason);
+    }
+    remove_client(connection_id, stable_id);
+}
+
+fn close_client(client: &Client, reason: EndConnexionReason) {
+    let shutdown = DtgShutdown::new(reason).as_bytes();
+    let _ = client.send_datagram(shutdown.clone()); // best effort, the close reason carry it anyway
+    client.unreliable_stream.stream.close(VarInt::from(u8::from(reason)), &shutdown);
+}
+
+/**
+ * This method close and release a client replaced in the CLIENT_MAP by a new
+ * connection from the same address. The new client isn't listened yet, so
+ * the subscriptions left under this ConnectionId are all the replaced one's.
+ *
+ * @param connection_id: ConnectionId
+ * @param replaced: Client, the client taken out of the CLIENT_MAP
+ */
+pub fn replace_client(connection_id: ConnectionId, replaced: Client) {
+    warn!("{} reconnected before its previous connection was closed, the previous one is closed.", connection_id);
+    close_client(&replaced, EndConnexionReason::Replaced);
+    OBJECT_REGISTRY.remove_client(connection_id);
+    TOPIC_REGISTRY.remove_client(connection_id);
+    release_client(connection_id, replaced);
+}
+
+/**
+ * This method remove a client and release every resource it owns. Nothing is
+ * done if the connection was already replaced by a new one from the same address.
+ * The client is taken out of the CLIENT_MAP first : a subscription handled
+ * meanwhile see it is gone and undo itself, so none is left after the cleanup.
+ *
+ * @param connection_id: ConnectionId, the client to remove
+ * @param stable_id: usize, the stable id of its connection
+ */
+pub fn remove_client(connection_id: ConnectionId, stable_id: usize) {
+    let client = match CLIENT_MAP.remove_if(&connection_id, |_, client| client.stable_id() == stable_id) {
+        Some((_, client)) => client,
+        None => return,
+    };
+
+    let objects = OBJECT_REGISTRY.remove_client(connection_id);
+    if objects > 0 && CONFIG.debug_object_handler {
+        debug!("{} unsubscribed from {} objects.", connection_id, objects);
+    }
+    let topics = TOPIC_REGISTRY.remove_client(connection_id);
+    if topics > 0 && CONFIG.debug_topic_handler {
+        debug!("{} unsubscribed from {} topics.", connection_id, topics);
+    }
+
+    release_client(connection_id, client);
+}
+
+// Release the slot of a client taken out of the CLIENT_MAP and log what it lost
+fn release_client(connection_id: ConnectionId, client: Client) {
+    release_slot();
+    let overflows = &client.stats.overflows;
+    let rate_limited = client.stats.rate_limiter.dropped.load(Ordering::Relaxed);
+    if rate_limited > 0 {
+        warn!("{} DtgData of {} were dropped by the rate limiter.", rate_limited, connection_id);
+    }
+    if overflows.dropped() > 0 {
+        warn!("{} packets of {} were dropped because the packet buffer was full (newest: {}, oldest: {}, by priority: {}).",
+            overflows.dropped(),
+            connection_id,
+            overflows.dropped_newest.load(Ordering::Relaxed),
+            overflows.dropped_oldest.load(Ordering::Relaxed),
+            overflows.dropped_by_priority.load(Ordering::Relaxed));
+    }
+    if client.egress.shed_count() > 0 {
+        let shed = &client.egress.shed;
+        warn!("{} unreliable DtgData to {} were shed by its egress queues (high: {}, normal: {}, low: {}).",
+            client.egress.shed_count(),
+            connection_id,
+            shed[0].load(Ordering::Relaxed),
+            shed[1].load(Ordering::Relaxed),
+            shed[2].load(Ordering::Relaxed));
+    }
+    if CONFIG.debug_client_manager {
+        debug!("Client {} removed.", connection_id);
+    }
+}
+
+async fn receive_datagrams(connection_id: ConnectionId, connection: &Connection, stats: &Arc<ClientStats>) -> ConnectionError {
+    loop {
+        match connection.read_datagram().await {
+            // quinn already give the datagram as reference-counted Bytes : no copy needed
+            Ok(bytes) => {
+                stats.touch();
+                if !check_rate_limit(connection_id, connection.stable_id(), stats, &bytes) {
+                    continue;
+                }
+                job_system::push_packet(Packet::new(connection_id, bytes, stats.clone())).await;
+            }
+            Err(err) => return err,
+        }
+    }
+}
+
+async fn receive_uni_streams(connection_id: ConnectionId, connection: &Connection, stats: &Arc<ClientStats>) -> ConnectionError {
+    loop {
+        match connection.accept_uni().await {
+            Ok(receiver) => {
+                tokio::spawn(read_stream(connection_id, connection.stable_id(), receiver, stats.clone()));
+            }
+            Err(err) => return err,
+        }
+    }
+}
+
+async fn receive_bi_streams(connection_id: ConnectionId, connection: &Connection, stats: &Arc<ClientStats>) -> ConnectionError {
+    loop {
+        match connection.accept_bi().await {
+            Ok((_sender, receiver)) => {
+                // Answers are sent through datagrams, the sender is closed
+                tokio::spawn(read_stream(connection_id, connection.stable_id(), receiver, stats.clone()));
+            }
+            Err(err) => return err,
+        }
+    }
+}
+
+/**
+ * Each stream opened by a client carry one datagram. It is read
+ * entirely into a pooled buffer and then given to the job system.
+ */
+async fn read_stream(connection_id: ConnectionId, stable_id: usize, mut receiver: RecvStream, stats: Arc<ClientStats>) {
+    let mut buffer = PACKET_POOL.acquire();
+
+    loop {
+        match receiver.read_chunk(CONFIG.packet_max_size, true).await {
+            Ok(Some(chunk)) => {
+                if buffer.len() + chunk.bytes.len() > CONFIG.packet_max_size {
+                    warn!("Stream from {} dropped : datagram bigger than {} bytes.", connection_id, CONFIG.packet_max_size);
+                    PACKET_POOL.release(buffer);
+                    return;
+                }
+                buffer.extend_from_slice(&chunk.bytes);
+            }
+            Ok(None) => break, // The stream is finished
+            Err(err) => {
+                if CONFIG.debug_client_manager {
+                    debug!("Failed to read a stream from {} : {}", connection_id, err);
+                }
+                PACKET_POOL.release(buffer);
+                return;
+            }
+        }
+    }
+
+    stats.touch();
+    if !check_rate_limit(connection_id, stable_id, &stats, &buffer) {
+        PACKET_POOL.release(buffer);
+        return;
+    }
+    job_system::push_packet(PACKET_POOL.packet(connection_id, buffer, stats)).await;
+}
+
+/**
+ * This method apply the rate limit of a client to a received datagram.
+ * Only DtgData are limited : control datagrams are always accepted.
+ *
+ * @param connection_id: ConnectionId, the sender
+ * @param stable_id: usize, the stable id of its connection
+ * @param stats: &ClientStats, the stats holding its rate limiter
+ * @param datagram: &[u8], the received datagram
+ *
+ * @return bool, true if the datagram can be given to the job system
+ */
+fn check_rate_limit(connection_id: ConnectionId, stable_id: usize, stats: &ClientStats, datagram: &[u8]) -> bool {
+    if !matches!(datagram.first().map(|code| DatagramType::from(*code)), Some(DatagramType::Data | DatagramType::DataWithAck)) {
+        return true;
+    }
+
+    match stats.rate_limiter.check(datagram.len()) {
+        RateVerdict::Accept => true,
+        RateVerdict::Drop => {
+            if CONFIG.debug_client_manager {
+                trace!("DtgData of {} dropped : rate limit exceeded.", connection_id);
+            }
+            false
+        }
+        RateVerdict::Disconnect => {
+            warn!("{} keep exceeding its rate limit, disconnecting it.", connection_id);
+            disconnect_client(connection_id, stable_id, EndConnexionReason::RateLimited);
+            false
+        }
+    }
+}
@@ -0,0 +1,52 @@
+// This document contain the deduplication window of a publisher. A DtgData
+// published with ack is retransmitted by its publisher until the broker
+// acknowledge it, so the same message may be received several times. The
+// window remember the last publish_dedup_window sequence numbers received on
+// each topic : a DtgData already seen is acknowledged again but not forwarded.
+// The window live as long as the connection, a message retransmitted after a
+// reconnection may then be forwarded twice (at-least-once delivery).
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
+
+use rekt_lib::libs::types::TopicId;
+
+use crate::CONFIG;
+
+#[derive(Debug, Default)]
+struct SequenceWindow {
+    order: VecDeque<u32>, // sequence numbers, oldest first
+    seen: HashSet<u32>,
+}
+
+#[derive(Debug, Default)]
+pub struct DedupWindow {
+    topics: Mutex<HashMap<TopicId, SequenceWindow>>,
+}
+
+impl DedupWindow {
+    /**
+     * This method record the sequence number of a DtgData, forgetting
+     * the oldest one of the topic once the window is full.
+     *
+     * @param topic_id: TopicId
+     * @param sequence_number: u32
+     *
+     * @return bool, false if the sequence number was already in the window
+     */
+    pub fn insert(&self, topic_id: TopicId, sequence_number: u32) -> bool {
+        let mut topics = self.topics.lock().unwrap();
+        let window = topics.entry(topic_id).or_default();
+        if !window.seen.insert(sequence_number) {
+            return false;
+        }
+
+        window.order.push_back(sequence_number);
+        if window.order.len() > CONFIG.publish_dedup_window {
+            if let Some(oldest) = window.order.pop_front() {
+                window.seen.remove(&oldest);
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,353 @@
+// This document contain the egress queues of a client. Each DtgData forwarded to
+// a client wait in the queue of its topic priority class, and a dedicated task
+// send them highest priority first. Unreliable datagrams are only handed to QUIC
+// when its datagram send buffer has room : under congestion the messages wait
+// here instead, where the priorities decide which ones go first. Once a client
+// has queue_size unreliable messages waiting, the oldest unreliable message of
+// the lowest priority class is shed to make room.
+//
+// Reliable messages don't use the datagram send buffer, so they are never
+// blocked behind a congested unreliable one. They are never shed either : the
+// ones delivered in order are written in the bidirectional stream of the client
+// by their own writer task, the other ones in their own stream, prioritized by
+// QUIC. A client with more than reliable_queue_size reliable messages waiting
+// or being written doesn't read them fast enough : it is disconnected.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use bytes::Bytes;
+use quinn::Connection;
+use rekt_lib::enums::delivery_mode::DeliveryMode;
+use rekt_lib::enums::end_connection_reason::EndConnexionReason;
+use rekt_lib::libs::types::TopicId;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use tokio::sync::Notify;
+
+use crate::CONFIG;
+use crate::clients::client::ConnectionId;
+use crate::clients::client_manager::disconnect_client;
+use crate::prelude::Result;
+use crate::streams::streams::RBiStream;
+
+pub const PRIORITY_COUNT: usize = 3;
+// Shortest wait for room in the datagram send buffer of a congested connection
+const MIN_CONGESTION_WAIT: Duration = Duration::from_millis(1);
+
+/**
+ * TopicPriority are the priority classes of the topics,
+ * set by the high_priority_topics and low_priority_topics
+ * patterns of the config.
+ */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TopicPriority {
+    High,
+    Normal,
+    Low,
+}
+
+impl TopicPriority {
+    pub fn of(topic_id: TopicId) -> TopicPriority {
+        if CONFIG.high_priority_topics.iter().any(|pattern| pattern.matches(topic_id)) {
+            TopicPriority::High
+        } else if CONFIG.low_priority_topics.iter().any(|pattern| pattern.matches(topic_id)) {
+            TopicPriority::Low
+        } else {
+            TopicPriority::Normal
+        }
+    }
+
+    // Index of the class queue, 0 is sent first
+    const fn index(self) -> usize {
+        match self {
+            TopicPriority::High => 0,
+            TopicPriority::Normal => 1,
+            TopicPriority::Low => 2,
+        }
+    }
+
+    // Priority of the QUIC streams carrying the class, the highest is sent first
+    const fn stream_priority(self) -> i32 {
+        match self {
+            TopicPriority::High => 1,
+            TopicPriority::Normal => 0,
+            TopicPriority::Low => -1,
+        }
+    }
+}
+
+const PRIORITIES: [TopicPriority; PRIORITY_COUNT] = [TopicPriority::High, TopicPriority::Normal, TopicPriority::Low];
+
+#[derive(Debug, Default)]
+struct Queues {
+    classes: [VecDeque<(Bytes, DeliveryMode)>; PRIORITY_COUNT], // indexed by TopicPriority::index
+    unreliable: usize, // amount of unreliable messages in every class
+    reliable: usize, // amount of reliable messages in every class
+}
+
+// Result of a pop
+#[derive(Debug, PartialEq)]
+pub enum Next {
+    Message(Bytes, DeliveryMode, TopicPriority),
+    Congested(usize), // no reliable message is waiting and the next unreliable one need this many more bytes in the datagram send buffer
+    Empty,
+}
+
+#[derive(Debug, Default)]
+pub struct EgressQueue {
+    queues: Mutex<Queues>,
+    notify: Notify,
+    reliable_backlog: AtomicUsize, // reliable messages waiting or being written
+    overflowed: AtomicBool, // set once the reliable backlog reached reliable_queue_size
+    pub shed: [AtomicU64; PRIORITY_COUNT], // unreliable messages shed per class, indexed as depths
+}
+
+impl EgressQueue {
+    /**
+     * This method queue a DtgData for the client. When the client already has
+     * queue_size unreliable messages waiting, the oldest one of the lowest class
+     * is shed. If every waiting one has a higher priority, the new one is shed.
+     * A reliable message over the reliable backlog is dropped and the client
+     * is disconnected by its egress task.
+     *
+     * @param datagram: Bytes, the DtgData
+     * @param delivery_mode: DeliveryMode, the delivery mode of the subscription
+     * @param priority: TopicPriority, the class of the topic
+     */
+    pub fn push(&self, datagram: Bytes, delivery_mode: DeliveryMode, priority: TopicPriority) {
+        let reliable = delivery_mode != DeliveryMode::Unreliable;
+        if reliable && self.reliable_backlog.fetch_add(1, Ordering::AcqRel) >= CONFIG.egress_reliable_queue_size {
+            self.reliable_backlog.fetch_sub(1, Ordering::AcqRel);
+            self.overflowed.store(true, Ordering::Release);
+            self.notify.notify_one();
+            return;
+        }
+
+        let mut queues = self.queues.lock().unwrap();
+        if reliable {
+            queues.reliable += 1;
+        } else {
+            if queues.unreliable >= CONFIG.egress_queue_size {
+                let shed = shed_lowest(&mut queues, priority);
+                self.shed[shed.unwrap_or(priority).index()].fetch_add(1, Ordering::Relaxed);
+                if shed.is_none() {
+                    return;
+                }
+            }
+            queues.unreliable += 1;
+        }
+        queues.classes[priority.index()].push_back((datagram, delivery_mode));
+        drop(queues);
+        self.notify.notify_one();
+    }
+
+    /**
+     * @return [usize; PRIORITY_COUNT], the messages waiting in each class, highest priority first
+     */
+    pub fn depths(&self) -> [usize; PRIORITY_COUNT] {
+        let queues = self.queues.lock().unwrap();
+        [queues.classes[0].len(), queues.classes[1].len(), queues.classes[2].len()]
+    }
+
+    /**
+     * @return usize, the reliable messages waiting or being written
+     */
+    pub fn reliable_backlog(&self) -> usize {
+        self.reliable_backlog.load(Ordering::Acquire)
+    }
+
+    /**
+     * @return bool, true once the reliable backlog reached reliable_queue_size
+     */
+    pub fn is_overflowed(&self) -> bool {
+        self.overflowed.load(Ordering::Acquire)
+    }
+
+    /**
+     * @return u64, the amount of unreliable messages shed whatever the class
+     */
+    pub fn shed_count(&self) -> u64 {
+        self.shed.iter().map(|counter| counter.load(Ordering::Relaxed)).sum()
+    }
+
+    // Called once a reliable message is written, or lost with its stream
+    fn reliable_done(&self) {
+        self.reliable_backlog.fetch_sub(1, Ordering::AcqRel);
+    }
+
+    /**
+     * This method take the next message to send : the oldest one of the highest
+     * class. An unreliable message is only taken if QUIC has room for it, else
+     * only the reliable messages can be taken, still highest class first.
+     *
+     * @param datagram_space: usize, the free space of the datagram send buffer
+     *
+     * @return Next
+     */
+    pub fn pop(&self, datagram_space: usize) -> Next {
+        let mut guard = self.queues.lock().unwrap();
+        let queues = &mut *guard;
+        let mut missing: Option<usize> = None; // room needed by the first unreliable message blocked
+
+        for priority in PRIORITIES {
+            let queue = &mut queues.classes[priority.index()];
+            let position = match queue.front() {
+                None => continue,
+                // An empty buffer accept anything, datagrams too large for the connection are refused by QUIC
+                Some((datagram, DeliveryMode::Unreliable)) if missing.is_none()
+                    && (datagram.len() <= datagram_space || datagram_space >= CONFIG.egress_datagram_buffer) => Some(0),
+                Some((datagram, DeliveryMode::Unreliable)) => {
+                    missing.get_or_insert(datagram.len().saturating_sub(datagram_space));
+                    if queues.reliable == 0 {
+                        continue;
+                    }
+                    queue.iter().position(|(_, delivery_mode)| *delivery_mode != DeliveryMode::Unreliable)
+                }
+                Some(_) => Some(0),
+            };
+
+            if let Some((datagram, delivery_mode)) = position.and_then(|position| queue.remove(position)) {
+                if delivery_mode == DeliveryMode::Unreliable {
+                    queues.unreliable -= 1;
+                } else {
+                    queues.reliable -= 1;
+                }
+                return Next::Message(datagram, delivery_mode, priority);
+            }
+        }
+
+        match missing {
+            Some(missing) => Next::Congested(missing),
+            None => Next::Empty,
+        }
+    }
+}
+
+// Remove the oldest unreliable message of the lowest class, if it is not above the given priority
+// Return the class of the removed message
+fn shed_lowest(queues: &mut Queues, priority: TopicPriority) -> Option<TopicPriority> {
+    for lowest in PRIORITIES.into_iter().rev() {
+        if lowest.index() < priority.index() {
+            return None;
+        }
+        let queue = &mut queues.classes[lowest.index()];
+        if let Some(position) = queue.iter().position(|(_, delivery_mode)| *delivery_mode == DeliveryMode::Unreliable) {
+            queue.remove(position);
+            queues.unreliable -= 1;
+            return Some(lowest);
+        }
+    }
+    None
+}
+
+/**
+ * This method run the egress task of a client : it send the queued messages
+ * until the connection is closed. The reliable messages delivered in order
+ * are given to the writer of the bidirectional stream opened on connection.
+ *
+ * @param connection_id: ConnectionId, the client
+ * @param connection: Connection, its QUIC connection
+ * @param bi_stream: RBiStream, its bidirectional stream
+ * @param egress: Arc<EgressQueue>, its egress queues
+ */
+pub async fn run_egress(connection_id: ConnectionId, connection: Connection, bi_stream: RBiStream, egress: Arc<EgressQueue>) {
+    let (ordered_sender, ordered_receiver) = unbounded_channel();
+    tokio::spawn(write_ordered(connection_id, bi_stream, ordered_receiver, egress.clone()));
+
+    while connection.close_reason().is_none() {
+        if egress.is_overflowed() {
+            warn!("{} doesn't read its reliable messages fast enough ({} waiting), it is disconnected.", connection_id, egress.reliable_backlog());
+            disconnect_client(connection_id, connection.stable_id(), EndConnexionReason::SlowConsumer);
+            break;
+        }
+
+        match egress.pop(connection.datagram_send_buffer_space()) {
+            Next::Message(datagram, DeliveryMode::Unreliable, _) => {
+                if let Err(err) = connection.send_datagram(datagram) {
+                    warn!("Failed to send data to {} : {}", connection_id, err);
+                }
+            }
+            Next::Message(datagram, DeliveryMode::ReliableOrdered, _) => {
+                // The writer only stop when the stream failed
+                if ordered_sender.send(datagram).is_err() {
+                    egress.reliable_done();
+                }
+            }
+            Next::Message(datagram, DeliveryMode::ReliableUnordered, priority) => {
+                let connection = connection.clone();
+                let egress = egress.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = write_unordered(connection, datagram, priority).await {
+                        warn!("Failed to send a reliable message to {} : {}", connection_id, err);
+                    }
+                    egress.reliable_done();
+                });
+            }
+            Next::Congested(missing) => {
+                // quinn doesn't tell when its datagram send buffer drain : a new message or
+                // the estimated time to send the missing bytes wake the task, whichever come first
+                tokio::select! {
+                    _ = egress.notify.notified() => {}
+                    _ = tokio::time::sleep(drain_delay(&connection, missing)) => {}
+                    _ = connection.closed() => {}
+                }
+            }
+            Next::Empty => {
+                // A message pushed since the pop left a permit : it is not missed
+                tokio::select! {
+                    _ = egress.notify.notified() => {}
+                    _ = connection.closed() => {}
+                }
+            }
+        }
+    }
+
+    if CONFIG.debug_egress {
+        debug!("Egress of {} stopped.", connection_id);
+    }
+}
+
+/**
+ * This method write the reliable messages delivered in order in the
+ * bidirectional stream of a client, one after the other. A slow reader
+ * only delay this task, never the egress task of the client.
+ *
+ * @param connection_id: ConnectionId, the client
+ * @param bi_stream: RBiStream, its bidirectional stream
+ * @param receiver: UnboundedReceiver<Bytes>, the messages given by the egress task
+ * @param egress: Arc<EgressQueue>, its egress queues
+ */
+async fn write_ordered(connection_id: ConnectionId, mut bi_stream: RBiStream, mut receiver: UnboundedReceiver<Bytes>, egress: Arc<EgressQueue>) {
+    while let Some(datagram) = receiver.recv().await {
+        let result = bi_stream.sender.write_chunk(datagram).await;
+        egress.reliable_done();
+        if let Err(err) = result {
+            warn!("Failed to write in the bidirectional stream of {} : {}", connection_id, err);
+            break;
+        }
+    }
+
+    // The messages still given are lost with the stream
+    receiver.close();
+    while receiver.try_recv().is_ok() {
+        egress.reliable_done();
+    }
+}
+
+// Estimate the time QUIC need to send the missing bytes of the datagram send buffer, at most one RTT
+fn drain_delay(connection: &Connection, missing: usize) -> Duration {
+    let rtt = connection.rtt();
+    let window = connection.congestion_state().window().max(1);
+    rtt.mul_f64(missing as f64 / window as f64).clamp(MIN_CONGESTION_WAIT, rtt.max(MIN_CONGESTION_WAIT))
+}
+
+// Send one message in a new unidirectional stream with the priority of its class
+async fn write_unordered(connection: Connection, datagram: Bytes, priority: TopicPriority) -> Result<()> {
+    let mut stream = connection.open_uni().await?;
+    let _ = stream.set_priority(priority.stream_priority()); // only fail if the stream is already closed
+    stream.write_chunk(datagram).await?;
+    stream.finish().await?;
+    Ok(())
+}
@@ -0,0 +1,95 @@
+// This document contain the latency tracking of a client. The ping sender
+// start a ping every period and the matching pong give a round trip time
+// sample. The RTT and its variation (jitter) are smoothed the same way TCP
+// does (RFC 6298), so a single slow pong doesn't change them too much.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use rekt_lib::libs::types::PingId;
+
+#[derive(Debug, Default)]
+struct LatencyState {
+    next_ping_id: PingId,
+    pending_ping: Option<(PingId, Instant)>, // only the last ping is waited for
+    rtt: Option<Duration>, // smoothed round trip time
+    jitter: Duration, // smoothed variation of the round trip time
+    samples: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct Latency {
+    state: Mutex<LatencyState>,
+}
+
+impl Latency {
+    /**
+     * This method record that a ping is sent to the client. A ping
+     * still pending is forgotten : its pong will be ignored.
+     *
+     * @return PingId, the identifier to put in the DtgPing
+     */
+    pub fn start_ping(&self) -> PingId {
+        let mut state = self.state.lock().unwrap();
+        let ping_id = state.next_ping_id;
+        state.next_ping_id = ping_id.wrapping_add(1);
+        state.pending_ping = Some((ping_id, Instant::now()));
+        ping_id
+    }
+
+    /**
+     * This method match a pong with the pending ping and update the smoothed values.
+     *
+     * @param ping_id: PingId, the identifier of the DtgPong
+     *
+     * @return Option<Duration>, the RTT sample, None if the pong doesn't match the pending ping
+     */
+    pub fn pong(&self, ping_id: PingId) -> Option<Duration> {
+        let mut state = self.state.lock().unwrap();
+        let sample = match state.pending_ping {
+            Some((pending_id, sent_at)) if pending_id == ping_id => sent_at.elapsed(),
+            _ => return None,
+        };
+        state.pending_ping = None;
+        state.samples += 1;
+
+        match state.rtt {
+            None => {
+                state.rtt = Some(sample);
+                state.jitter = sample / 2;
+            }
+            Some(rtt) => {
+                let variation = rtt.abs_diff(sample);
+                state.jitter = (state.jitter * 3 + variation) / 4;
+                state.rtt = Some((rtt * 7 + sample) / 8);
+            }
+        }
+        Some(sample)
+    }
+
+    /**
+     * @return Option<Duration>, the smoothed RTT, None until the first pong is received
+     */
+    pub fn rtt(&self) -> Option<Duration> {
+        self.state.lock().unwrap().rtt
+    }
+
+    pub fn jitter(&self) -> Duration {
+        self.state.lock().unwrap().jitter
+    }
+
+    /**
+     * This method return the extra time to wait for an answer of the client,
+     * computed as the retransmission timeout of RFC 6298 : RTT + 4 * jitter.
+     *
+     * @return Duration, zero until the first pong is received
+     */
+    pub fn timeout_margin(&self) -> Duration {
+        let state = self.state.lock().unwrap();
+        state.rtt.map_or(Duration::ZERO, |rtt| rtt + state.jitter * 4)
+    }
+
+    pub fn sample_count(&self) -> u64 {
+        self.state.lock().unwrap().samples
+    }
+}
@@ -1 +1,6 @@
-pub mod client;
\ No newline at end of file
+pub mod client;
+pub mod client_manager;
+pub mod dedup_window;
+pub mod egress;
+pub mod latency;
+pub mod rate_limiter;
\ No newline at end of file
@@ -0,0 +1,172 @@
+// This document contain the publish rate limiter of a client. Each client own
+// two token buckets, one counting DtgData messages and one counting their bytes,
+// refilled continuously at the rate set in the [rate_limit] table of the config.
+// A bucket can hold one second of traffic, so short bursts are accepted.
+// Datagrams that don't fit in the buckets are dropped before reaching the job
+// system. Each second in which something is dropped is a strike : a client
+// reaching max_strikes consecutive strikes must be disconnected.
+
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::CONFIG;
+
+const STRIKE_WINDOW: Duration = Duration::from_secs(1);
+
+/**
+ * A RateLimit is the amount of DtgData a client can publish per second.
+ * 0 means unlimited.
+ */
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct RateLimit {
+    pub messages_per_second: u32,
+    pub bytes_per_second: u64,
+}
+
+impl RateLimit {
+    /**
+     * @param identity: &str, the identity given by the authenticator
+     *
+     * @return RateLimit, the limit of this identity or the global one
+     */
+    pub fn of(identity: &str) -> RateLimit {
+        CONFIG.rate_limit_identities.get(identity)
+            .copied()
+            .unwrap_or(CONFIG.rate_limit)
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self.messages_per_second == 0 && self.bytes_per_second == 0
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RateVerdict {
+    Accept,
+    Drop,
+    Disconnect, // the client kept exceeding its limit for max_strikes seconds
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64, // tokens added per second, also the capacity of the bucket
+    tokens: f64,
+}
+
+impl TokenBucket {
+    fn new(rate: u64) -> Option<TokenBucket> {
+        (rate > 0).then_some(TokenBucket { rate: rate as f64, tokens: rate as f64 })
+    }
+
+    fn refill(&mut self, elapsed: Duration) {
+        self.tokens = (self.tokens + self.rate * elapsed.as_secs_f64()).min(self.rate);
+    }
+
+    // A cost bigger than the bucket is accepted once it is full, the bucket then go into debt
+    fn can_take(&self, cost: f64) -> bool {
+        self.tokens >= cost.min(self.rate)
+    }
+
+    fn take(&mut self, cost: f64) {
+        self.tokens -= cost;
+    }
+}
+
+#[derive(Debug)]
+struct RateLimiterState {
+    messages: Option<TokenBucket>,
+    bytes: Option<TokenBucket>,
+    last_refill: Instant,
+    window_start: Instant,
+    dropped_in_window: bool,
+    strikes: u16,
+}
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    unlimited: bool,
+    state: Mutex<RateLimiterState>,
+    pub dropped: AtomicU64, // DtgData dropped since the client is connected
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> RateLimiter {
+        let now = Instant::now();
+        RateLimiter {
+            unlimited: limit.is_unlimited(),
+            state: Mutex::new(RateLimiterState {
+                messages: TokenBucket::new(limit.messages_per_second.into()),
+                bytes: TokenBucket::new(limit.bytes_per_second),
+                last_refill: now,
+                window_start: now,
+                dropped_in_window: false,
+                strikes: 0,
+            }),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /**
+     * This method check if a DtgData of the client can be accepted,
+     * and consume its cost from the buckets if it is.
+     *
+     * @param size: usize, the size of the datagram in bytes
+     *
+     * @return RateVerdict
+     */
+    pub fn check(&self, size: usize) -> RateVerdict {
+        self.check_at(size, Instant::now())
+    }
+
+    // Same as check, at the given time
+    pub(crate) fn check_at(&self, size: usize, now: Instant) -> RateVerdict {
+        if self.unlimited {
+            return RateVerdict::Accept;
+        }
+
+        let mut state = self.state.lock().unwrap();
+
+        // A whole window without drop end the strikes series
+        let window_elapsed = now.duration_since(state.window_start);
+        if window_elapsed >= STRIKE_WINDOW {
+            if !state.dropped_in_window || window_elapsed >= STRIKE_WINDOW * 2 {
+                state.strikes = 0;
+            }
+            state.window_start = now;
+            state.dropped_in_window = false;
+        }
+
+        let elapsed = now.duration_since(state.last_refill);
+        state.last_refill = now;
+        let size = size as f64;
+        let state = &mut *state;
+        for bucket in [&mut state.messages, &mut state.bytes].into_iter().flatten() {
+            bucket.refill(elapsed);
+        }
+
+        let accepted = state.messages.as_ref().is_none_or(|bucket| bucket.can_take(1.0))
+            && state.bytes.as_ref().is_none_or(|bucket| bucket.can_take(size));
+        if accepted {
+            if let Some(bucket) = state.messages.as_mut() {
+                bucket.take(1.0);
+            }
+            if let Some(bucket) = state.bytes.as_mut() {
+                bucket.take(size);
+            }
+            return RateVerdict::Accept;
+        }
+
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+        if !state.dropped_in_window {
+            state.dropped_in_window = true;
+            state.strikes = state.strikes.saturating_add(1);
+        }
+
+        if CONFIG.rate_limit_max_strikes > 0 && state.strikes >= CONFIG.rate_limit_max_strikes {
+            RateVerdict::Disconnect
+        } else {
+            RateVerdict::Drop
+        }
+    }
+}
@@ -3,24 +3,111 @@
 // @author : GuicLuca (lucasguichard127@gmail.com)
 // date : 14/03/2023
 
+use std::collections::HashMap;
 use std::fs;
 use std::io::Error;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use serde::{Deserialize, Serialize};
 use toml;
 
+use crate::acl::TopicPattern;
+use crate::clients::rate_limiter::RateLimit;
+use crate::job_system::OverflowPolicy;
+use crate::topics::durable_log::FsyncPolicy;
+
 
 // Contain the Server table of the toml file
 #[derive(Serialize, Deserialize, Debug)]
 struct ConfigTomlServer {
     port: Option<String>,
+    bind_addresses: Option<Vec<String>>,
     packet_buffer_size: Option<u16>,
+    packet_max_size: Option<u32>,
+    max_clients: Option<u32>,
 }
 
 // Contain the Period table of the toml file
 #[derive(Serialize, Deserialize, Debug)]
 struct ConfigTomlPeriod {
     heartbeat_period: Option<u16>,
+    heartbeat_timeout: Option<u16>,
     ping_period: Option<u16>,
+    shutdown_grace_period: Option<u16>,
+    handshake_timeout: Option<u16>,
+}
+
+// Contain the JobSystem table of the toml file
+#[derive(Serialize, Deserialize, Debug)]
+struct ConfigTomlJobSystem {
+    worker_count: Option<u16>,
+    overflow_policy: Option<String>,
+    overflow_reserve: Option<u8>,
+}
+
+// Contain the Tls table of the toml file
+#[derive(Serialize, Deserialize, Debug)]
+struct ConfigTomlTls {
+    certificate_chain: Option<String>,
+    private_key: Option<String>,
+    self_signed_certificate: Option<String>,
+    self_signed_key: Option<String>,
+}
+
+// Contain the Auth table of the toml file
+#[derive(Serialize, Deserialize, Debug)]
+struct ConfigTomlAuth {
+    credentials_file: Option<String>,
+    acl_file: Option<String>,
+}
+
+// Contain the RateLimit table of the toml file
+#[derive(Serialize, Deserialize, Debug)]
+struct ConfigTomlRateLimit {
+    messages_per_second: Option<u32>,
+    bytes_per_second: Option<u64>,
+    max_strikes: Option<u16>,
+    identities: Option<HashMap<String, ConfigTomlRateLimitIdentity>>,
+}
+
+// Contain the limits of one identity in the RateLimit table
+#[derive(Serialize, Deserialize, Debug)]
+struct ConfigTomlRateLimitIdentity {
+    messages_per_second: Option<u32>,
+    bytes_per_second: Option<u64>,
+}
+
+// Contain the Topics table of the toml file
+#[derive(Serialize, Deserialize, Debug)]
+struct ConfigTomlTopics {
+    retained_topics: Option<Vec<String>>,
+    history_topics: Option<Vec<String>>,
+    history_size: Option<u16>,
+    history_duration: Option<u32>,
+    reliable_ordered_topics: Option<Vec<String>>,
+    reliable_unordered_topics: Option<Vec<String>>,
+    publish_dedup_window: Option<u16>,
+    high_priority_topics: Option<Vec<String>>,
+    low_priority_topics: Option<Vec<String>>,
+}
+
+// Contain the Durability table of the toml file
+#[derive(Serialize, Deserialize, Debug)]
+struct ConfigTomlDurability {
+    durable_topics: Option<Vec<String>>,
+    log_directory: Option<String>,
+    segment_size: Option<u64>,
+    retention_duration: Option<u64>,
+    retention_size: Option<u64>,
+    fsync_policy: Option<String>,
+    fsync_period: Option<u16>,
+}
+
+// Contain the Egress table of the toml file
+#[derive(Serialize, Deserialize, Debug)]
+struct ConfigTomlEgress {
+    queue_size: Option<u32>,
+    reliable_queue_size: Option<u32>,
+    datagram_buffer: Option<u32>,
 }
 
 // Contain the Debug table of the toml file
@@ -34,6 +121,7 @@ struct ConfigTomlDebug {
     debug_topic_handler: Option<bool>,
     debug_client_manager: Option<bool>,
     debug_object_handler: Option<bool>,
+    debug_egress: Option<bool>,
 }
 
 // Used to load every table of the toml file
@@ -42,6 +130,13 @@ struct ConfigToml {
     server: Option<ConfigTomlServer>,
     debug: Option<ConfigTomlDebug>,
     period: Option<ConfigTomlPeriod>,
+    job_system: Option<ConfigTomlJobSystem>,
+    tls: Option<ConfigTomlTls>,
+    auth: Option<ConfigTomlAuth>,
+    rate_limit: Option<ConfigTomlRateLimit>,
+    topics: Option<ConfigTomlTopics>,
+    durability: Option<ConfigTomlDurability>,
+    egress: Option<ConfigTomlEgress>,
 }
 
 // This is the final structure that contain every
@@ -49,9 +144,46 @@ struct ConfigToml {
 #[derive(Debug)]
 pub struct Config {
     pub port: u16,
+    pub bind_addresses: Vec<SocketAddr>,
     pub packet_buffer_size: u16,
+    pub packet_max_size: usize,
+    pub max_clients: usize,
     pub heart_beat_period: u16,
+    pub heartbeat_timeout: u16,
     pub ping_period: u16,
+    pub shutdown_grace_period: u16,
+    pub handshake_timeout: u16,
+    pub worker_count: usize,
+    pub overflow_policy: OverflowPolicy,
+    pub overflow_reserve: u8,
+    pub tls_certificate_chain: Option<String>,
+    pub tls_private_key: Option<String>,
+    pub tls_self_signed_certificate: String,
+    pub tls_self_signed_key: String,
+    pub auth_credentials_file: Option<String>,
+    pub auth_acl_file: Option<String>,
+    pub rate_limit: RateLimit,
+    pub rate_limit_max_strikes: u16,
+    pub rate_limit_identities: HashMap<String, RateLimit>,
+    pub retained_topics: Vec<TopicPattern>,
+    pub history_topics: Vec<TopicPattern>,
+    pub history_size: usize,
+    pub history_duration: u32,
+    pub reliable_ordered_topics: Vec<TopicPattern>,
+    pub reliable_unordered_topics: Vec<TopicPattern>,
+    pub publish_dedup_window: usize,
+    pub high_priority_topics: Vec<TopicPattern>,
+    pub low_priority_topics: Vec<TopicPattern>,
+    pub durable_topics: Vec<TopicPattern>,
+    pub durable_log_directory: String,
+    pub durable_segment_size: u64,
+    pub durable_retention_duration: u64,
+    pub durable_retention_size: u64,
+    pub durable_fsync_policy: FsyncPolicy,
+    pub durable_fsync_period: u16,
+    pub egress_queue_size: usize,
+    pub egress_reliable_queue_size: usize,
+    pub egress_datagram_buffer: usize,
     pub debug_level: String,
     pub debug_datagram_handler: bool,
     pub debug_ping_sender: bool,
@@ -60,6 +192,7 @@ pub struct Config {
     pub debug_topic_handler: bool,
     pub debug_client_manager: bool,
     pub debug_object_handler: bool,
+    pub debug_egress: bool,
 }
 
 impl Config {
@@ -97,6 +230,13 @@ impl Config {
                     server: None,
                     period: None,
                     debug: None,
+                    job_system: None,
+                    tls: None,
+                    auth: None,
+                    rate_limit: None,
+                    topics: None,
+                    durability: None,
+                    egress: None,
                 }
             }
         };
@@ -105,7 +245,7 @@ impl Config {
         info!("Creating server config table...");
 
         // 4.1 - Server variables
-        let (port, packet_buffer_size): (u16, u16) = match config_toml.server {
+        let (port, bind_addresses, packet_buffer_size, packet_max_size, max_clients): (u16, Vec<SocketAddr>, u16, usize, usize) = match config_toml.server {
             Some(server) => {
                 let port: u16 =  match server.port.unwrap_or_else(|| {
                     println!("Missing field port in table server.");
@@ -120,41 +260,311 @@ impl Config {
                     }
                 };
 
+                let bind_addresses: Vec<SocketAddr> = server.bind_addresses.unwrap_or_else(|| {
+                    println!("Missing field bind_addresses in table server.");
+                    vec!("0.0.0.0".to_owned()) // Default value if none found
+                }).iter()
+                    .filter_map(|address| Config::parse_bind_address(address, port))
+                    .collect();
+
                 let packet_buffer_size: u16 = server.packet_buffer_size.unwrap_or_else(|| {
                     println!("Missing field packet_buffer_size in table server.");
                     1000u16
                 });
 
-                (port, packet_buffer_size)
+                let packet_max_size: usize = server.packet_max_size.unwrap_or_else(|| {
+                    println!("Missing field packet_max_size in table server.");
+                    65535u32
+                }) as usize;
+
+                let max_clients: usize = server.max_clients.unwrap_or_else(|| {
+                    println!("Missing field max_clients in table server.");
+                    0u32
+                }) as usize;
+
+                (port, bind_addresses, packet_buffer_size, packet_max_size, max_clients)
             }
             None => {
                 println!("Missing table server.");
-                (3838, 1000) // Default value if none found
+                (3838, vec!(SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 3838)), 1000, 65535, 0) // Default value if none found
             }
         };
 
         // 4.2 - Period variables
         info!("Creating period config table...");
-        let (heartbeat_period, ping_period): (u16, u16) = match config_toml.period {
+        let (heartbeat_period, heartbeat_timeout, ping_period, shutdown_grace_period, handshake_timeout): (u16, u16, u16, u16, u16) = match config_toml.period {
             Some(period) => {
                 let hb_period = period.heartbeat_period.unwrap_or_else(|| {
                     println!("Missing field heartbeat_period in table period.");
                     5 // Default value if none found
-                });
+                }).max(1);
+                let hb_timeout = period.heartbeat_timeout.unwrap_or_else(|| {
+                    println!("Missing field heartbeat_timeout in table period.");
+                    3 // Default value if none found
+                }).max(2);
                 let ping_period = period.ping_period.unwrap_or_else(|| {
                     println!("Missing field ping_period in table period.");
                     5 // Default value if none found
                 });
+                let grace_period = period.shutdown_grace_period.unwrap_or_else(|| {
+                    println!("Missing field shutdown_grace_period in table period.");
+                    5 // Default value if none found
+                });
 
-                (hb_period, ping_period)
+                let handshake_timeout = period.handshake_timeout.unwrap_or_else(|| {
+                    println!("Missing field handshake_timeout in table period.");
+                    5 // Default value if none found
+                }).max(1);
+
+                (hb_period, hb_timeout, ping_period, grace_period, handshake_timeout)
             }
             None => {
                 println!("Missing table period.");
-                (5, 5) // Default value if none found
+                (5, 3, 5, 5, 5) // Default value if none found
             }
         };
 
-        // 4.3 - Debug variables
+        // 4.3 - Job system variables
+        info!("Creating job system config table...");
+        let (worker_count, overflow_policy, overflow_reserve): (usize, OverflowPolicy, u8) = match config_toml.job_system {
+            Some(job_system) => {
+                let worker_count = job_system.worker_count.unwrap_or_else(|| {
+                    println!("Missing field worker_count in table job_system.");
+                    0 // Default value if none found
+                }) as usize;
+                let overflow_policy_name = job_system.overflow_policy.unwrap_or_else(|| {
+                    println!("Missing field overflow_policy in table job_system.");
+                    "drop_newest".to_string() // Default value if none found
+                });
+                let overflow_policy = OverflowPolicy::from_name(&overflow_policy_name).unwrap_or_else(|| {
+                    println!("Unknown overflow_policy {} in table job_system, drop_newest is used.", overflow_policy_name);
+                    OverflowPolicy::DropNewest
+                });
+                let overflow_reserve = job_system.overflow_reserve.unwrap_or_else(|| {
+                    println!("Missing field overflow_reserve in table job_system.");
+                    10 // Default value if none found
+                }).min(100);
+
+                (worker_count, overflow_policy, overflow_reserve)
+            }
+            None => {
+                println!("Missing table job_system.");
+                (0, OverflowPolicy::DropNewest, 10) // Default value if none found
+            }
+        };
+        // 0 worker means one worker per core
+        let worker_count = if worker_count == 0 { num_cpus::get() } else { worker_count };
+
+        // 4.4 - Tls variables
+        info!("Creating tls config table...");
+        let (tls_certificate_chain,
+            tls_private_key,
+            tls_self_signed_certificate,
+            tls_self_signed_key): (Option<String>, Option<String>, String, String) = match config_toml.tls {
+            Some(tls) => {
+                // An empty path means that the self-signed certificate is used
+                let certificate_chain = tls.certificate_chain.filter(|path| !path.is_empty());
+                let private_key = tls.private_key.filter(|path| !path.is_empty());
+                let self_signed_certificate = tls.self_signed_certificate.unwrap_or_else(|| {
+                    println!("Missing field self_signed_certificate in table tls.");
+                    "./certs/self_signed_cert.pem".to_string() // Default value if none found
+                });
+                let self_signed_key = tls.self_signed_key.unwrap_or_else(|| {
+                    println!("Missing field self_signed_key in table tls.");
+                    "./certs/self_signed_key.pem".to_string() // Default value if none found
+                });
+
+                (certificate_chain, private_key, self_signed_certificate, self_signed_key)
+            }
+            None => {
+                println!("Missing table tls.");
+                (None, None, "./certs/self_signed_cert.pem".to_string(), "./certs/self_signed_key.pem".to_string()) // Default value if none found
+            }
+        };
+
+        // 4.5 - Auth variables
+        info!("Creating auth config table...");
+        let (auth_credentials_file, auth_acl_file): (Option<String>, Option<String>) = match config_toml.auth {
+            Some(auth) => {
+                // An empty path means that the authentication (or the access control) is disabled
+                (auth.credentials_file.filter(|path| !path.is_empty()),
+                 auth.acl_file.filter(|path| !path.is_empty()))
+            }
+            None => {
+                println!("Missing table auth.");
+                (None, None) // Default value if none found
+            }
+        };
+
+        // 4.6 - Rate limit variables
+        info!("Creating rate limit config table...");
+        let (rate_limit, rate_limit_max_strikes, rate_limit_identities): (RateLimit, u16, HashMap<String, RateLimit>) = match config_toml.rate_limit {
+            Some(rate_limit) => {
+                let global = RateLimit {
+                    messages_per_second: rate_limit.messages_per_second.unwrap_or_else(|| {
+                        println!("Missing field messages_per_second in table rate_limit.");
+                        0 // Default value if none found
+                    }),
+                    bytes_per_second: rate_limit.bytes_per_second.unwrap_or_else(|| {
+                        println!("Missing field bytes_per_second in table rate_limit.");
+                        0 // Default value if none found
+                    }),
+                };
+                let max_strikes = rate_limit.max_strikes.unwrap_or_else(|| {
+                    println!("Missing field max_strikes in table rate_limit.");
+                    5 // Default value if none found
+                });
+                // A field missing in an identity table keep the global value
+                let identities = rate_limit.identities.unwrap_or_default()
+                    .into_iter()
+                    .map(|(identity, limit)| (identity, RateLimit {
+                        messages_per_second: limit.messages_per_second.unwrap_or(global.messages_per_second),
+                        bytes_per_second: limit.bytes_per_second.unwrap_or(global.bytes_per_second),
+                    }))
+                    .collect();
+
+                (global, max_strikes, identities)
+            }
+            None => {
+                println!("Missing table rate_limit.");
+                (RateLimit::default(), 5, HashMap::default()) // Default value if none found
+            }
+        };
+
+        // 4.7 - Topics variables
+        info!("Creating topics config table...");
+        let (retained_topics,
+            history_topics,
+            history_size,
+            history_duration,
+            reliable_ordered_topics,
+            reliable_unordered_topics,
+            publish_dedup_window,
+            high_priority_topics,
+            low_priority_topics) = match config_toml.topics {
+            Some(topics) => {
+                let retained_topics = parse_topic_patterns("retained_topics", topics.retained_topics.unwrap_or_else(|| {
+                    println!("Missing field retained_topics in table topics.");
+                    Vec::new() // Default value if none found
+                }));
+                let history_topics = parse_topic_patterns("history_topics", topics.history_topics.unwrap_or_else(|| {
+                    println!("Missing field history_topics in table topics.");
+                    Vec::new() // Default value if none found
+                }));
+                let history_size = topics.history_size.unwrap_or_else(|| {
+                    println!("Missing field history_size in table topics.");
+                    100 // Default value if none found
+                }).max(1) as usize;
+                let history_duration = topics.history_duration.unwrap_or_else(|| {
+                    println!("Missing field history_duration in table topics.");
+                    0 // Default value if none found
+                });
+                let reliable_ordered_topics = parse_topic_patterns("reliable_ordered_topics", topics.reliable_ordered_topics.unwrap_or_else(|| {
+                    println!("Missing field reliable_ordered_topics in table topics.");
+                    Vec::new() // Default value if none found
+                }));
+                let reliable_unordered_topics = parse_topic_patterns("reliable_unordered_topics", topics.reliable_unordered_topics.unwrap_or_else(|| {
+                    println!("Missing field reliable_unordered_topics in table topics.");
+                    Vec::new() // Default value if none found
+                }));
+                let publish_dedup_window = topics.publish_dedup_window.unwrap_or_else(|| {
+                    println!("Missing field publish_dedup_window in table topics.");
+                    1024 // Default value if none found
+                }).max(1) as usize;
+                let high_priority_topics = parse_topic_patterns("high_priority_topics", topics.high_priority_topics.unwrap_or_else(|| {
+                    println!("Missing field high_priority_topics in table topics.");
+                    Vec::new() // Default value if none found
+                }));
+                let low_priority_topics = parse_topic_patterns("low_priority_topics", topics.low_priority_topics.unwrap_or_else(|| {
+                    println!("Missing field low_priority_topics in table topics.");
+                    Vec::new() // Default value if none found
+                }));
+
+                (retained_topics, history_topics, history_size, history_duration, reliable_ordered_topics, reliable_unordered_topics, publish_dedup_window, high_priority_topics, low_priority_topics)
+            }
+            None => {
+                println!("Missing table topics.");
+                (Vec::new(), Vec::new(), 100, 0, Vec::new(), Vec::new(), 1024, Vec::new(), Vec::new()) // Default value if none found
+            }
+        };
+
+        // 4.8 - Durability variables
+        info!("Creating durability config table...");
+        let (durable_topics,
+            durable_log_directory,
+            durable_segment_size,
+            durable_retention_duration,
+            durable_retention_size,
+            durable_fsync_policy,
+            durable_fsync_period): (Vec<TopicPattern>, String, u64, u64, u64, FsyncPolicy, u16) = match config_toml.durability {
+            Some(durability) => {
+                let durable_topics = parse_topic_patterns("durable_topics", durability.durable_topics.unwrap_or_else(|| {
+                    println!("Missing field durable_topics in table durability.");
+                    Vec::new() // Default value if none found
+                }));
+                let log_directory = durability.log_directory.unwrap_or_else(|| {
+                    println!("Missing field log_directory in table durability.");
+                    "./data/topics".to_string() // Default value if none found
+                });
+                let segment_size = durability.segment_size.unwrap_or_else(|| {
+                    println!("Missing field segment_size in table durability.");
+                    16 * 1024 * 1024 // Default value if none found
+                }).max(1024);
+                let retention_duration = durability.retention_duration.unwrap_or_else(|| {
+                    println!("Missing field retention_duration in table durability.");
+                    0 // Default value if none found
+                });
+                let retention_size = durability.retention_size.unwrap_or_else(|| {
+                    println!("Missing field retention_size in table durability.");
+                    0 // Default value if none found
+                });
+                let fsync_policy_name = durability.fsync_policy.unwrap_or_else(|| {
+                    println!("Missing field fsync_policy in table durability.");
+                    "periodic".to_string() // Default value if none found
+                });
+                let fsync_policy = FsyncPolicy::from_name(&fsync_policy_name).unwrap_or_else(|| {
+                    println!("Unknown fsync_policy {} in table durability, periodic is used.", fsync_policy_name);
+                    FsyncPolicy::Periodic
+                });
+                let fsync_period = durability.fsync_period.unwrap_or_else(|| {
+                    println!("Missing field fsync_period in table durability.");
+                    1 // Default value if none found
+                }).max(1);
+
+                (durable_topics, log_directory, segment_size, retention_duration, retention_size, fsync_policy, fsync_period)
+            }
+            None => {
+                println!("Missing table durability.");
+                (Vec::new(), "./data/topics".to_string(), 16 * 1024 * 1024, 0, 0, FsyncPolicy::Periodic, 1) // Default value if none found
+            }
+        };
+
+        // 4.9 - Egress variables
+        info!("Creating egress config table...");
+        let (egress_queue_size, egress_reliable_queue_size, egress_datagram_buffer): (usize, usize, usize) = match config_toml.egress {
+            Some(egress) => {
+                let queue_size = egress.queue_size.unwrap_or_else(|| {
+                    println!("Missing field queue_size in table egress.");
+                    1024 // Default value if none found
+                }).max(1) as usize;
+                let reliable_queue_size = egress.reliable_queue_size.unwrap_or_else(|| {
+                    println!("Missing field reliable_queue_size in table egress.");
+                    4096 // Default value if none found
+                }).max(1) as usize;
+                let datagram_buffer = egress.datagram_buffer.unwrap_or_else(|| {
+                    println!("Missing field datagram_buffer in table egress.");
+                    65536 // Default value if none found
+                }).max(4096) as usize;
+
+                (queue_size, reliable_queue_size, datagram_buffer)
+            }
+            None => {
+                println!("Missing table egress.");
+                (1024, 4096, 65536) // Default value if none found
+            }
+        };
+
+        // 4.10 - Debug variables
         info!("Creating debug config table...");
         let (debug_level,
             debug_datagram_handler,
@@ -163,7 +573,8 @@ impl Config {
             debug_heartbeat_checker,
             debug_topic_handler,
             debug_client_manager,
-            debug_object_handler,): (String, bool, bool, bool, bool, bool, bool, bool) = match config_toml.debug {
+            debug_object_handler,
+            debug_egress): (String, bool, bool, bool, bool, bool, bool, bool, bool) = match config_toml.debug {
             Some(debug) => {
                 let d_level: String = debug.debug_level.unwrap_or_else(|| {
                     println!("Missing field debug_level in table debug.");
@@ -197,21 +608,62 @@ impl Config {
                     println!("Missing field debug_object_handler in table debug.");
                     true // Default value if none found
                 });
+                let d_egress = debug.debug_egress.unwrap_or_else(|| {
+                    println!("Missing field debug_egress in table debug.");
+                    true // Default value if none found
+                });
 
-                (d_level, d_datagram, d_ping, d_data, d_heart, d_topic, d_manager, d_object)
+                (d_level, d_datagram, d_ping, d_data, d_heart, d_topic, d_manager, d_object, d_egress)
             }
             None => {
                 println!("Missing table debug.");
-                ("trace".to_string(), true, true, true, true, true, true, true) // Default value if none found
+                ("trace".to_string(), true, true, true, true, true, true, true, true) // Default value if none found
             }
         };
 
 
         Config {
             port,
+            bind_addresses,
             packet_buffer_size,
+            packet_max_size,
+            max_clients,
             heart_beat_period: heartbeat_period,
+            heartbeat_timeout,
             ping_period,
+            shutdown_grace_period,
+            handshake_timeout,
+            worker_count,
+            overflow_policy,
+            overflow_reserve,
+            tls_certificate_chain,
+            tls_private_key,
+            tls_self_signed_certificate,
+            tls_self_signed_key,
+            auth_credentials_file,
+            auth_acl_file,
+            rate_limit,
+            rate_limit_max_strikes,
+            rate_limit_identities,
+            retained_topics,
+            history_topics,
+            history_size,
+            history_duration,
+            reliable_ordered_topics,
+            reliable_unordered_topics,
+            publish_dedup_window,
+            high_priority_topics,
+            low_priority_topics,
+            durable_topics,
+            durable_log_directory,
+            durable_segment_size,
+            durable_retention_duration,
+            durable_retention_size,
+            durable_fsync_policy,
+            durable_fsync_period,
+            egress_queue_size,
+            egress_reliable_queue_size,
+            egress_datagram_buffer,
             debug_level,
             debug_datagram_handler,
             debug_ping_sender,
@@ -219,7 +671,44 @@ impl Config {
             debug_heartbeat_checker,
             debug_topic_handler,
             debug_client_manager,
-            debug_object_handler
+            debug_object_handler,
+            debug_egress
+        }
+    }
+
+    /**
+     * This method convert an address of the bind_addresses field into a socket address.
+     * The address can be an ip (IPv4, IPv6, wildcard) using the server port, or an ip with its own port.
+     *
+     * @param address: &str, ex: "0.0.0.0", "::", "[::1]:4000", "192.168.1.10:3838"
+     * @param port: u16, port used when the address doesn't contain one
+     *
+     * @return Option<SocketAddr>, None if the address is invalid
+     */
+    fn parse_bind_address(address: &str, port: u16) -> Option<SocketAddr> {
+        if let Ok(socket_addr) = address.parse::<SocketAddr>() {
+            return Some(socket_addr);
+        }
+
+        match address.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+            Ok(ip) => Some(SocketAddr::new(ip, port)),
+            Err(err) => {
+                println!("Invalid bind address {}, it will be ignored. Error:\n{}", address, err);
+                None
+            }
         }
     }
-}
\ No newline at end of file
+}
+
+// Parse the topic patterns of a field, the invalid ones are ignored
+fn parse_topic_patterns(field: &str, patterns: Vec<String>) -> Vec<TopicPattern> {
+    patterns.iter()
+        .filter_map(|pattern| {
+            let topic_pattern = TopicPattern::parse(pattern);
+            if topic_pattern.is_none() {
+                println!("Invalid topic pattern {} in {}, ignored.", pattern, field);
+            }
+            topic_pattern
+        })
+        .collect()
+}
@@ -12,6 +12,15 @@ pub enum Error {
     #[error("[InitializationError] - {0}")]
     InitializationError(String),
 
+    #[error("[ProtocolError] - {0}")]
+    Protocol(String),
+
+    #[error("[TlsError] - {0}")]
+    Tls(String),
+
+    #[error("[AuthError] - {0}")]
+    Auth(String),
+
     #[error(transparent)]
     CertificateError(#[from] rcgen::RcgenError),
 
@@ -0,0 +1,104 @@
+// This document contain the handler of DATA datagrams. Each payload published
+// on a topic is forwarded as is to every subscriber of this topic, including its
+// publisher when it is subscribed too, with the delivery mode of its
+// subscription : QUIC datagram or reliable stream. Payloads
+// published on a topic the publisher has no right on are dropped, and the
+// publisher receive a SubFailure NACK explaining why. The last payload of a
+// retained topic, and the last payloads of a history topic, are kept for its
+// future subscribers. Payloads of a durable topic are also written on disk.
+// Forwarded payloads wait in the egress queue of the priority class of their topic.
+//
+// A payload published with ack is acknowledged to its publisher with a
+// DtgDataAck once handled. The publisher retransmit it until then : a sequence
+// number already in its dedup window is acknowledged again but not forwarded.
+// A refused one is answered with a DtgDataAck of the DataNack type instead, so
+// the publisher stop retransmitting it.
+// Subscribers always receive it as a plain DtgData.
+
+use bytes::{Bytes, BytesMut};
+use rekt_lib::datagrams::data_request::{DtgData, DtgDataAck};
+use rekt_lib::datagrams::topic_request::DtgTopicRequestNack;
+use rekt_lib::enums::datagram_type::DatagramType;
+use rekt_lib::enums::topic_response::TopicResponse;
+
+use crate::{CLIENT_MAP, CONFIG, DURABLE_LOG, RETAINED_VALUES, TOPIC_HISTORY, TOPIC_REGISTRY};
+use crate::clients::client::ConnectionId;
+use crate::clients::egress::TopicPriority;
+use crate::handlers::datagram_handler::send_datagram;
+use crate::prelude::{Error, Result};
+
+/**
+ * This method handle a payload published by a client on a topic.
+ *
+ * @param source: ConnectionId, the publisher
+ * @param datagram: &Bytes, the datagram
+ *
+ * @return Result<()>
+ */
+pub fn handle_data(source: ConnectionId, datagram: &Bytes) -> Result<()> {
+    let data = DtgData::try_from(&datagram[..]).map_err(|err| Error::Protocol(err.to_string()))?;
+
+    match CLIENT_MAP.get(&source).map(|client| client.permissions.can_publish(data.topic_id)) {
+        None => return Ok(()), // disconnected meanwhile
+        Some(false) => {
+            if CONFIG.debug_data_handler {
+                debug!("{} is not allowed to publish on topic {}, payload dropped", source, data.topic_id);
+            }
+            let reason = format!("Not allowed to publish on topic {}.", data.topic_id);
+            send_datagram(source, DtgTopicRequestNack::new(TopicResponse::SubFailure, &reason).as_bytes())?;
+            if data.requires_ack() {
+                send_datagram(source, DtgDataAck::nack(data.topic_id, data.sequence_number).as_bytes())?;
+            }
+            return Ok(());
+        }
+        Some(true) => {}
+    }
+
+    let datagram = if data.requires_ack() {
+        let is_new = match CLIENT_MAP.get(&source) {
+            Some(client) => client.dedup_window.insert(data.topic_id, data.sequence_number),
+            None => return Ok(()), // disconnected meanwhile
+        };
+        if !is_new {
+            if CONFIG.debug_data_handler {
+                debug!("Sequence {} of topic {} already received from {}, acknowledged again", data.sequence_number, data.topic_id, source);
+            }
+            return send_datagram(source, DtgDataAck::new(data.topic_id, data.sequence_number).as_bytes());
+        }
+        as_plain_data(datagram)
+    } else {
+        datagram.clone()
+    };
+
+    RETAINED_VALUES.store(data.topic_id, &datagram);
+    DURABLE_LOG.append(data.topic_id, &datagram);
+
+    // A client subscribing meanwhile get the message either in its history or from here
+    let subscribers = TOPIC_HISTORY.record(data.topic_id, &datagram, || TOPIC_REGISTRY.subscribers_of(data.topic_id));
+    if CONFIG.debug_data_handler {
+        trace!("{} bytes published by {} on topic {} (sequence {}), forwarded to {} subscribers",
+            data.size, source, data.topic_id, data.sequence_number, subscribers.len());
+    }
+
+    let priority = TopicPriority::of(data.topic_id);
+    for (subscriber, delivery_mode) in subscribers {
+        let client = match CLIENT_MAP.get(&subscriber) {
+            Some(client) => client,
+            None => continue, // disconnected meanwhile
+        };
+        // The datagram is shared between all subscribers : only its reference counter is increased
+        client.send_data(datagram.clone(), delivery_mode, priority);
+    }
+
+    if data.requires_ack() {
+        send_datagram(source, DtgDataAck::new(data.topic_id, data.sequence_number).as_bytes())?;
+    }
+    Ok(())
+}
+
+// Copy a DtgData published with ack with the type of a plain DtgData
+fn as_plain_data(datagram: &Bytes) -> Bytes {
+    let mut plain = BytesMut::from(&datagram[..]);
+    plain[0] = u8::from(DatagramType::Data);
+    plain.freeze()
+}
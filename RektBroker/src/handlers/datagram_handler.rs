@@ -0,0 +1,168 @@
+// This document contain the entry point of every packet computed by the job
+// system. The packet is decoded according to its datagram type and then given
+// to the corresponding handler. Datagrams that can't be decoded are answered
+// with the matching NACK when the protocol define one, otherwise they are
+// counted as protocol errors of the client.
+
+use quinn::VarInt;
+use rekt_lib::datagrams::connect_requests::{DtgConnect, DtgConnectAck, DtgConnectNack};
+use rekt_lib::datagrams::heartbeat_requests::DtgHeartbeat;
+use rekt_lib::datagrams::latency_requests::{DtgPing, DtgPong};
+use rekt_lib::datagrams::miscellaneous_requests::{DtgServerStatus, DtgServerStatusACK};
+use rekt_lib::datagrams::shutdown_request::DtgShutdown;
+use rekt_lib::enums::datagram_type::{DatagramType, display_datagram_type};
+
+use crate::{CLIENT_MAP, CONFIG};
+use crate::clients::client::{ConnectionId, Packet};
+use crate::clients::client_manager;
+use crate::handlers::{data_handler, object_handler, topic_handler};
+use crate::prelude::{ClientId, Error, Result};
+
+/**
+ * This method decode a packet and route it to the handler of its datagram type.
+ *
+ * @param packet: &Packet, the packet to compute
+ */
+pub async fn handle_datagram(packet: &Packet) {
+    let source = packet.source;
+
+    // 1 - The client may have been removed while the packet was waiting in the buffer
+    if !CLIENT_MAP.contains_key(&source) {
+        return;
+    }
+
+    // 2 - build the datagram struct + respond to it
+    let buffer: &[u8] = packet.as_bytes();
+    if buffer.is_empty() {
+        count_protocol_error(source, "Empty datagram");
+        return;
+    }
+    let datagram_type = DatagramType::from(buffer[0]);
+    if CONFIG.debug_datagram_handler {
+        trace!("{} datagram received from {}", display_datagram_type(datagram_type), source);
+    }
+
+    let result = match datagram_type {
+        DatagramType::Connect => handle_connect(source, buffer),
+        DatagramType::Heartbeat => handle_heartbeat(source, buffer),
+        DatagramType::Ping => handle_ping(source, buffer),
+        DatagramType::Pong => handle_pong(source, buffer),
+        DatagramType::ServerStatus => handle_server_status(source, buffer),
+        DatagramType::TopicRequest => topic_handler::handle_topic_request(source, buffer),
+        DatagramType::ObjectRequest => object_handler::handle_object_request(source, buffer),
+        DatagramType::Data | DatagramType::DataWithAck => data_handler::handle_data(source, &packet.datagram),
+        DatagramType::Shutdown => handle_shutdown(source, buffer),
+        // Unknown types and datagrams that only the broker is supposed to send
+        _ => Err(Error::Protocol(format!("Unexpected {} datagram (0x{:02X})", display_datagram_type(datagram_type), buffer[0]))),
+    };
+
+    if let Err(err) = result {
+        match err {
+            Error::Protocol(reason) => count_protocol_error(source, &reason),
+            err => warn!("Failed to handle a {} datagram from {} : {}", display_datagram_type(datagram_type), source, err),
+        }
+    }
+}
+
+/**
+ * This method increase the protocol error counter of a client.
+ *
+ * @param source: ConnectionId, the client that sent the invalid datagram
+ * @param reason: &str, description of the error
+ */
+pub fn count_protocol_error(source: ConnectionId, reason: &str) {
+    if let Some(mut client) = CLIENT_MAP.get_mut(&source) {
+        client.protocol_errors += 1;
+        warn!("Protocol error n°{} from {} : {}", client.protocol_errors, source, reason);
+    }
+}
+
+/**
+ * This method send a datagram to a client if it is still connected.
+ *
+ * @param target: ConnectionId, the receiver
+ * @param datagram: Vec<u8>, the datagram as bytes
+ *
+ * @return Result<()>
+ */
+pub fn send_datagram(target: ConnectionId, datagram: Vec<u8>) -> Result<()> {
+    match CLIENT_MAP.get(&target) {
+        Some(client) => client.send_datagram(datagram),
+        None => Ok(()), // The client is gone, nothing to do
+    }
+}
+
+// The first DtgConnect is handled by the client manager handshake. A connected
+// client sending it again (after a lost ACK for example) is simply acknowledged again.
+fn handle_connect(source: ConnectionId, buffer: &[u8]) -> Result<()> {
+    if let Err(reason) = DtgConnect::try_from(buffer) {
+        return send_datagram(source, DtgConnectNack::new(reason).as_bytes());
+    }
+
+    let peer_id: ClientId = match CLIENT_MAP.get(&source) {
+        Some(client) => client.id,
+        None => return Ok(()),
+    };
+    send_datagram(source, DtgConnectAck::new(peer_id, CONFIG.heart_beat_period).as_bytes())
+}
+
+fn handle_heartbeat(source: ConnectionId, buffer: &[u8]) -> Result<()> {
+    DtgHeartbeat::try_from(buffer).map_err(|err| Error::Protocol(err.to_string()))?;
+
+    if CONFIG.debug_heartbeat_checker {
+        trace!("Heartbeat received from {}", source);
+    }
+    Ok(())
+}
+
+fn handle_ping(source: ConnectionId, buffer: &[u8]) -> Result<()> {
+    let ping = DtgPing::try_from(buffer).map_err(|err| Error::Protocol(err.to_string()))?;
+
+    send_datagram(source, DtgPong::new(ping.ping_id).as_bytes())
+}
+
+fn handle_pong(source: ConnectionId, buffer: &[u8]) -> Result<()> {
+    let pong = DtgPong::try_from(buffer).map_err(|err| Error::Protocol(err.to_string()))?;
+
+    let client = match CLIENT_MAP.get(&source) {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+    match client.stats.latency.pong(pong.ping_id) {
+        Some(sample) => {
+            if CONFIG.debug_ping_sender {
+                trace!("Pong {} received from {} : RTT {:?} (smoothed {:?}, jitter {:?})",
+                    pong.ping_id, source, sample, client.stats.latency.rtt().unwrap_or_default(), client.stats.latency.jitter());
+            }
+        }
+        None => {
+            // Late pong of a previous period, it is simply ignored
+            if CONFIG.debug_ping_sender {
+                debug!("Unexpected pong {} received from {}", pong.ping_id, source);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn handle_server_status(source: ConnectionId, buffer: &[u8]) -> Result<()> {
+    DtgServerStatus::try_from(buffer).map_err(|err| Error::Protocol(err.to_string()))?;
+
+    send_datagram(source, DtgServerStatusACK::new(CLIENT_MAP.len() as ClientId).as_bytes())
+}
+
+fn handle_shutdown(source: ConnectionId, buffer: &[u8]) -> Result<()> {
+    let shutdown = DtgShutdown::try_from(buffer).map_err(|err| Error::Protocol(err.to_string()))?;
+    info!("Shutdown received from {} (reason : {:?})", source, shutdown.reason);
+
+    // Close the connection first so the receive loops stop, then release the client
+    let stable_id = match CLIENT_MAP.get(&source) {
+        Some(client) => {
+            client.unreliable_stream.stream.close(VarInt::from_u32(0), b"Shutdown received");
+            client.stable_id()
+        }
+        None => return Ok(()),
+    };
+    client_manager::remove_client(source, stable_id);
+    Ok(())
+}
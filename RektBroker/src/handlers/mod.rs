@@ -0,0 +1,4 @@
+pub mod datagram_handler;
+pub mod data_handler;
+pub mod object_handler;
+pub mod topic_handler;
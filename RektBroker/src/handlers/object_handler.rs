@@ -0,0 +1,73 @@
+// This document contain the handler of OBJECT_REQUEST datagrams. Every action
+// is applied on the object registry and answered with an ACK or a NACK holding
+// the action flag, so the client can match the response with its request.
+// Subscribing to an object containing a topic the client isn't allowed to
+// subscribe to is answered with a NACK.
+
+use rekt_lib::datagrams::object_requests::{DtgObjectRequest, DtgObjectRequestACK, DtgObjectRequestNACK};
+use rekt_lib::enums::object_request_action::ObjectRequestAction;
+use rekt_lib::libs::types::ObjectId;
+
+use crate::{CLIENT_MAP, CONFIG, OBJECT_REGISTRY};
+use crate::clients::client::ConnectionId;
+use crate::handlers::datagram_handler::send_datagram;
+use crate::prelude::Result;
+
+/**
+ * This method handle every action a client can do on an object.
+ *
+ * @param source: ConnectionId, the client that sent the request
+ * @param buffer: &[u8], the datagram
+ *
+ * @return Result<()>
+ */
+pub fn handle_object_request(source: ConnectionId, buffer: &[u8]) -> Result<()> {
+    let request = match DtgObjectRequest::try_from(buffer) {
+        Ok(request) => request,
+        Err(reason) => {
+            let flag = buffer.get(3).copied().unwrap_or(u8::from(ObjectRequestAction::Unknown));
+            return send_datagram(source, DtgObjectRequestNACK::new(flag, 0, reason).as_bytes());
+        }
+    };
+
+    if CONFIG.debug_object_handler {
+        debug!("{:?} request received from {} on object {}", request.flag, source, request.object_id);
+    }
+
+    let object_id = request.object_id;
+    let result = match request.flag {
+        ObjectRequestAction::Create => OBJECT_REGISTRY.create(object_id, request.payload),
+        ObjectRequestAction::Update => OBJECT_REGISTRY.update(object_id, request.payload).map(|_| object_id),
+        ObjectRequestAction::Delete => OBJECT_REGISTRY.delete(object_id).map(|_| object_id),
+        ObjectRequestAction::Subscribe => subscribe(source, object_id).map(|_| object_id),
+        ObjectRequestAction::Unsubscribe => OBJECT_REGISTRY.unsubscribe(object_id, source).map(|_| object_id),
+        ObjectRequestAction::Unknown => Err("Unknown object action."),
+    };
+
+    let flag = u8::from(request.flag);
+    match result {
+        Ok(final_object_id) => {
+            if CONFIG.debug_object_handler {
+                debug!("{:?} request of {} on object {} succeeded", request.flag, source, final_object_id);
+            }
+            send_datagram(source, DtgObjectRequestACK::new(flag, object_id, final_object_id).as_bytes())
+        }
+        Err(reason) => send_datagram(source, DtgObjectRequestNACK::new(flag, object_id, reason).as_bytes()),
+    }
+}
+
+fn subscribe(source: ConnectionId, object_id: ObjectId) -> core::result::Result<(), &'static str> {
+    let (permissions, stable_id) = match CLIENT_MAP.get(&source) {
+        Some(client) => (client.permissions.clone(), client.stable_id()),
+        None => return Err("Client disconnected."),
+    };
+    OBJECT_REGISTRY.subscribe(object_id, source, &permissions)?;
+
+    // The client is taken out of the CLIENT_MAP before its objects are cleaned : if it
+    // was removed or replaced while subscribing, the subscription is undone here
+    if CLIENT_MAP.get(&source).is_none_or(|client| client.stable_id() != stable_id) {
+        let _ = OBJECT_REGISTRY.unsubscribe(object_id, source);
+        return Err("Client disconnected.");
+    }
+    Ok(())
+}
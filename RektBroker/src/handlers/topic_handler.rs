@@ -0,0 +1,151 @@
+// This document contain the handler of TOPIC_REQUEST datagrams. Subscribe and
+// unsubscribe requests update the topic registry and are answered with an ACK
+// on success or a NACK explaining the failure. Subscriptions to a topic the
+// client has no right on are denied with a SubFailure NACK. The last value of
+// a retained topic is sent right after the ACK of a subscription, or its whole
+// history if the client asked for it and the topic keep one. A client resuming
+// a durable topic receive its messages from the requested sequence number.
+// When the history or the durable log have nothing to replay, the client still
+// receive the last value of the topic.
+// A subscription may ask for its own delivery mode, replacing the one of the topic.
+
+use rekt_lib::datagrams::topic_request::{DtgTopicRequest, DtgTopicRequestAck, DtgTopicRequestNack, TOPIC_OPTION_HISTORY, TOPIC_OPTION_RESUME, TOPIC_OPTION_RETAINED};
+use rekt_lib::enums::topic_action::TopicAction;
+use rekt_lib::enums::topic_response::TopicResponse;
+use rekt_lib::libs::types::TopicId;
+
+use crate::{CLIENT_MAP, CONFIG, DURABLE_LOG, RETAINED_VALUES, TOPIC_HISTORY, TOPIC_REGISTRY};
+use crate::clients::client::ConnectionId;
+use crate::clients::egress::TopicPriority;
+use crate::handlers::datagram_handler::send_datagram;
+use crate::prelude::Result;
+
+/**
+ * This method handle a subscribe or unsubscribe request of a client.
+ *
+ * @param source: ConnectionId, the client that sent the request
+ * @param buffer: &[u8], the datagram
+ *
+ * @return Result<()>
+ */
+pub fn handle_topic_request(source: ConnectionId, buffer: &[u8]) -> Result<()> {
+    let request = match DtgTopicRequest::try_from(buffer) {
+        Ok(request) => request,
+        Err(reason) => {
+            return send_datagram(source, DtgTopicRequestNack::new(TopicResponse::Unknown, reason).as_bytes());
+        }
+    };
+
+    if CONFIG.debug_topic_handler {
+        debug!("{:?} request received from {} on topic {}", request.flag, source, request.topic_id);
+    }
+
+    let response = match request.flag {
+        TopicAction::Subscribe => return subscribe(source, &request),
+        TopicAction::Unsubscribe => unsubscribe(source, request.topic_id),
+        TopicAction::Unknown => DtgTopicRequestNack::new(TopicResponse::Unknown, "Unknown topic action.").as_bytes(),
+    };
+    send_datagram(source, response)
+}
+
+/**
+ * Subscribing twice to the same topic is not an error : clients
+ * replay their subscriptions after each reconnection. The durable
+ * messages, the history or the last value of the topic is sent after the ACK.
+ *
+ * @param source: ConnectionId, the client that sent the request
+ * @param request: &DtgTopicRequest, the subscribe request and its options
+ *
+ * @return Result<()>
+ */
+fn subscribe(source: ConnectionId, request: &DtgTopicRequest) -> Result<()> {
+    let topic_id = request.topic_id;
+    let stable_id = match CLIENT_MAP.get(&source).map(|client| (client.permissions.can_subscribe(topic_id), client.stable_id())) {
+        None => return Ok(()), // disconnected meanwhile
+        Some((false, _)) => {
+            if CONFIG.debug_topic_handler {
+                debug!("{} is not allowed to subscribe to topic {}", source, topic_id);
+            }
+            let reason = format!("Not allowed to subscribe to topic {}.", topic_id);
+            return send_datagram(source, DtgTopicRequestNack::new(TopicResponse::SubFailure, &reason).as_bytes());
+        }
+        Some((true, stable_id)) => stable_id,
+    };
+
+    // The history is taken along with the subscription : the messages published after are delivered live
+    let wants_history = request.has_option(TOPIC_OPTION_HISTORY) && TOPIC_HISTORY.has_history(topic_id);
+    let history = if wants_history {
+        TOPIC_HISTORY.subscribe(topic_id, || TOPIC_REGISTRY.subscribe(topic_id, source, request.delivery_mode())).1
+    } else {
+        TOPIC_REGISTRY.subscribe(topic_id, source, request.delivery_mode());
+        Vec::new()
+    };
+
+    // The client may have been removed while subscribing : its cleanup is then already done
+    if CLIENT_MAP.get(&source).is_none_or(|client| client.stable_id() != stable_id) {
+        TOPIC_REGISTRY.unsubscribe(topic_id, source);
+        return Ok(());
+    }
+
+    if request.has_option(TOPIC_OPTION_RETAINED) {
+        RETAINED_VALUES.mark(topic_id);
+    }
+
+    if CONFIG.debug_topic_handler {
+        let delivery_mode = TOPIC_REGISTRY.delivery_mode_of(topic_id, source).unwrap_or_default();
+        debug!("{} subscribed to topic {} ({:?})", source, topic_id, delivery_mode);
+    }
+    send_datagram(source, DtgTopicRequestAck::new(topic_id, TopicResponse::SubSuccess).as_bytes())?;
+
+    // The durable messages and the history already end with the last value of the topic,
+    // it is only sent when they replay nothing
+    if request.has_option(TOPIC_OPTION_RESUME) && DURABLE_LOG.is_durable(topic_id) {
+        let connection = match CLIENT_MAP.get(&source) {
+            Some(client) => client.unreliable_stream.stream.clone(),
+            None => return Ok(()),
+        };
+        DURABLE_LOG.replay(source, connection, topic_id, request.resume_from, move || send_retained_value(source, topic_id));
+        return Ok(());
+    }
+    if wants_history {
+        let connection = match CLIENT_MAP.get(&source) {
+            Some(client) => client.unreliable_stream.stream.clone(),
+            None => return Ok(()),
+        };
+        let replayed = TOPIC_HISTORY.replay(source, connection, topic_id, history);
+        if CONFIG.debug_topic_handler {
+            debug!("{} messages of the topic {} history replayed to {}", replayed, topic_id, source);
+        }
+        if replayed > 0 {
+            return Ok(());
+        }
+    }
+
+    send_retained_value(source, topic_id);
+    Ok(())
+}
+
+// Send the last value of a retained topic to a subscriber, with the delivery mode of its subscription
+fn send_retained_value(source: ConnectionId, topic_id: TopicId) {
+    let (last_value, delivery_mode) = match (RETAINED_VALUES.last_value(topic_id), TOPIC_REGISTRY.delivery_mode_of(topic_id, source)) {
+        (Some(last_value), Some(delivery_mode)) => (last_value, delivery_mode),
+        _ => return, // nothing retained, or unsubscribed meanwhile
+    };
+    if let Some(client) = CLIENT_MAP.get(&source) {
+        if CONFIG.debug_topic_handler {
+            debug!("Retained value of topic {} sent to {}", topic_id, source);
+        }
+        client.send_data(last_value, delivery_mode, TopicPriority::of(topic_id));
+    }
+}
+
+fn unsubscribe(source: ConnectionId, topic_id: TopicId) -> Vec<u8> {
+    if !TOPIC_REGISTRY.unsubscribe(topic_id, source) {
+        return DtgTopicRequestNack::new(TopicResponse::UnsubFailure, "Not subscribed to this topic.").as_bytes();
+    }
+
+    if CONFIG.debug_topic_handler {
+        debug!("{} unsubscribed from topic {}", source, topic_id);
+    }
+    DtgTopicRequestAck::new(topic_id, TopicResponse::UnsubSuccess).as_bytes()
+}
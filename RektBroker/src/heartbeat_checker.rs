@@ -0,0 +1,68 @@
+// This document contain the heartbeat checker. Every heartbeat period it look
+// at the last activity of each client : a client idle for one period receive a
+// DtgHeartbeatRequest, and a client silent for `heartbeat_timeout` periods is
+// disconnected with a DtgShutdown(TimeOut) and all of its resources released.
+// Both delays are extended by the latency margin of the client, so clients
+// behind a slow link are not disconnected too early.
+
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use rekt_lib::datagrams::heartbeat_requests::DtgHeartbeatRequest;
+use rekt_lib::enums::end_connection_reason::EndConnexionReason;
+use tokio::time::MissedTickBehavior;
+
+use crate::{CLIENT_MAP, CONFIG, prelude, SERVER_IS_RUNNING};
+use crate::clients::client::ConnectionId;
+use crate::clients::client_manager;
+
+pub async fn init_heartbeat_checker() -> prelude::Result<()> {
+    let period = Duration::from_secs(CONFIG.heart_beat_period.into());
+    let timeout = period * CONFIG.heartbeat_timeout.into();
+    info!("- Heartbeat checker started (period {:?}, timeout {:?}).", period, timeout);
+
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    while SERVER_IS_RUNNING.load(Ordering::Acquire) {
+        interval.tick().await;
+        check_clients(period, timeout);
+    }
+
+    debug!("Heartbeat checker stopped.");
+    Ok(())
+}
+
+/**
+ * This method send a heartbeat request to every idle client
+ * and disconnect the ones that stayed silent too long.
+ *
+ * @param period: Duration, the heartbeat period
+ * @param timeout: Duration, idle time after which a client is disconnected
+ */
+fn check_clients(period: Duration, timeout: Duration) {
+    let mut timed_out: Vec<(ConnectionId, usize)> = Vec::new();
+    let heartbeat_request = DtgHeartbeatRequest::new().as_bytes();
+
+    for client in CLIENT_MAP.iter() {
+        let idle_time = client.stats.idle_time();
+        let margin = client.stats.latency.timeout_margin();
+
+        if idle_time >= timeout + margin {
+            // Removed once the iteration is done : the map can't be modified while iterated
+            timed_out.push((client.connection_id, client.stable_id()));
+        } else if idle_time >= period + margin {
+            if CONFIG.debug_heartbeat_checker {
+                debug!("{} idle for {:?}, heartbeat requested.", client.connection_id, idle_time);
+            }
+            if let Err(err) = client.send_datagram(heartbeat_request.clone()) {
+                warn!("Failed to send a heartbeat request to {} : {}", client.connection_id, err);
+            }
+        }
+    }
+
+    for (connection_id, stable_id) in timed_out {
+        warn!("{} didn't send anything for more than {:?}, it is disconnected.", connection_id, timeout);
+        client_manager::disconnect_client(connection_id, stable_id, EndConnexionReason::TimeOut);
+    }
+}
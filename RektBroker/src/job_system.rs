@@ -1,54 +1,360 @@
-use std::sync::atomic::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 
-use tokio::{join, task};
+use crossbeam_queue::ArrayQueue;
+use rekt_lib::enums::datagram_type::DatagramType;
+use tokio::sync::Notify;
 use tokio::task::JoinHandle;
 
-use crate::{PACKET_BUFFER, prelude, SERVER_IS_RUNNING, WORKER_CONDVAR};
+use crate::{CONFIG, PACKET_BUFFER, PACKET_POOL, prelude, SERVER_IS_RUNNING};
+use crate::clients::client::{ClientStats, ConnectionId, Packet};
+use crate::handlers::datagram_handler;
+
+/**
+ * OverflowPolicy are all the way the packet buffer
+ * can behave once a shard is full.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    DropNewest, // The incoming packet is dropped
+    DropOldest, // The oldest queued packet is dropped to make room
+    DropByPriority, // Data packets are dropped first, a part of the shard is kept for control packets
+    PauseBusiest, // The busiest connections stop being read until there is room again
+}
+
+impl OverflowPolicy {
+    /**
+     * This method convert the overflow_policy field of the config.
+     *
+     * @param value: &str, ex: "drop_newest"
+     *
+     * @return Option<OverflowPolicy>, None if the policy is unknown
+     */
+    pub fn from_name(value: &str) -> Option<OverflowPolicy> {
+        match value {
+            "drop_newest" => Some(OverflowPolicy::DropNewest),
+            "drop_oldest" => Some(OverflowPolicy::DropOldest),
+            "drop_by_priority" => Some(OverflowPolicy::DropByPriority),
+            "pause_busiest" => Some(OverflowPolicy::PauseBusiest),
+            _ => None,
+        }
+    }
+}
+
+/**
+ * OverflowCounters count the packets dropped by each policy and how many
+ * times a receive loop has been paused. They are kept for the whole buffer
+ * and for each client.
+ */
+#[derive(Debug, Default)]
+pub struct OverflowCounters {
+    pub dropped_newest: AtomicU64,
+    pub dropped_oldest: AtomicU64,
+    pub dropped_by_priority: AtomicU64,
+    pub paused: AtomicU64,
+}
+
+impl OverflowCounters {
+    fn count_drop(&self, policy: OverflowPolicy) {
+        let counter = match policy {
+            OverflowPolicy::DropNewest | OverflowPolicy::PauseBusiest => &self.dropped_newest,
+            OverflowPolicy::DropOldest => &self.dropped_oldest,
+            OverflowPolicy::DropByPriority => &self.dropped_by_priority,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /**
+     * @return u64, the amount of packets dropped whatever the policy
+     */
+    pub fn dropped(&self) -> u64 {
+        self.dropped_newest.load(Ordering::Relaxed)
+            + self.dropped_oldest.load(Ordering::Relaxed)
+            + self.dropped_by_priority.load(Ordering::Relaxed)
+    }
+}
+
+///
+/// The packet buffer is split in one shard per worker. Every packet of a
+/// connection always go to the same shard and each shard is only emptied by
+/// its own worker : the requests of a client are then computed in their
+/// arrival order, while different clients are still computed in parallel.
+///
+pub struct PacketBuffer {
+    shards: Vec<PacketShard>,
+    policy: OverflowPolicy,
+    pub overflows: OverflowCounters,
+}
+
+struct PacketShard {
+    queue: ArrayQueue<Packet>,
+    notify: Notify, // wake up the worker when a packet is pushed
+    room: Notify, // wake up the paused receive loops when a packet is popped
+    high_watermark: usize, // above it, Data packets are dropped or the busiest connections paused
+    active_sources: AtomicUsize, // amount of connections with at least one queued packet
+}
+
+impl PacketShard {
+    fn enqueued(&self, stats: &ClientStats) {
+        if stats.queued_packets.fetch_add(1, Ordering::AcqRel) == 0 {
+            self.active_sources.fetch_add(1, Ordering::AcqRel);
+        }
+    }
+
+    fn dequeued(&self, stats: &ClientStats) {
+        if stats.queued_packets.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.active_sources.fetch_sub(1, Ordering::AcqRel);
+        }
+    }
+
+    fn try_push(&self, packet: Packet) -> Result<(), Packet> {
+        self.enqueued(&packet.stats);
+        self.queue.push(packet).inspect_err(|packet| self.dequeued(&packet.stats))
+    }
+
+    /**
+     * A connection is busy when the shard is above its high watermark and the
+     * connection hold more than its fair share of the queued packets.
+     */
+    fn is_busy(&self, stats: &ClientStats) -> bool {
+        if self.queue.is_full() {
+            return true;
+        }
+        if self.queue.len() < self.high_watermark {
+            return false;
+        }
+        let fair_share = self.queue.capacity() / self.active_sources.load(Ordering::Acquire).max(1);
+        stats.queued_packets.load(Ordering::Acquire) >= fair_share
+    }
+}
+
+impl PacketBuffer {
+    /**
+     * @param shard_count: usize, amount of worker
+     * @param capacity: usize, total amount of packets the buffer can hold
+     * @param policy: OverflowPolicy, behavior of a full shard
+     * @param reserve: u8, percentage of each shard kept for control packets and quiet connections
+     */
+    pub fn new(shard_count: usize, capacity: usize, policy: OverflowPolicy, reserve: u8) -> PacketBuffer {
+        let shard_count = shard_count.max(1);
+        let shard_capacity = (capacity / shard_count).max(1);
+        let reserved = shard_capacity * usize::from(reserve.min(100)) / 100;
+
+        PacketBuffer {
+            shards: (0..shard_count).map(|_| PacketShard {
+                queue: ArrayQueue::new(shard_capacity),
+                notify: Notify::new(),
+                room: Notify::new(),
+                high_watermark: shard_capacity - reserved,
+                active_sources: AtomicUsize::new(0),
+            }).collect(),
+            policy,
+            overflows: OverflowCounters::default(),
+        }
+    }
+
+    /**
+     * This method return the index of the shard that compute the packets of a connection.
+     *
+     * @param source: &ConnectionId
+     *
+     * @return usize
+     */
+    pub fn shard_of(&self, source: &ConnectionId) -> usize {
+        let mut hasher = DefaultHasher::new();
+        source.hash(&mut hasher);
+        (hasher.finish() % self.shards.len() as u64) as usize
+    }
+
+    /**
+     * This method queue a packet in the shard of its connection and wake up the shard worker.
+     * When the shard is full, the overflow policy decide which packet is dropped or
+     * pause the caller until there is room. Drops are counted for the buffer and for the
+     * client that sent the dropped packet.
+     *
+     * @param packet: Packet
+     *
+     * @return Option<Packet>, the dropped packet if any
+     */
+    pub async fn push(&self, packet: Packet) -> Option<Packet> {
+        let shard = &self.shards[self.shard_of(&packet.source)];
+
+        let dropped = match self.policy {
+            OverflowPolicy::DropNewest => shard.try_push(packet).err(),
+            OverflowPolicy::DropOldest => {
+                shard.enqueued(&packet.stats);
+                shard.queue.force_push(packet).inspect(|oldest| shard.dequeued(&oldest.stats))
+            }
+            OverflowPolicy::DropByPriority => {
+                if is_control_packet(&packet) || shard.queue.len() < shard.high_watermark {
+                    shard.try_push(packet).err()
+                } else {
+                    Some(packet)
+                }
+            }
+            OverflowPolicy::PauseBusiest => self.push_when_room(shard, packet).await.err(),
+        };
+
+        // If the worker is busy, the permit is kept and its next wait won't sleep.
+        shard.notify.notify_one();
+
+        if let Some(packet) = &dropped {
+            self.overflows.count_drop(self.policy);
+            packet.stats.overflows.count_drop(self.policy);
+        }
+        dropped
+    }
+
+    /**
+     * This method wait until the connection isn't busy anymore and then queue the packet.
+     * The packet is only dropped if the server stop in the meantime.
+     */
+    async fn push_when_room(&self, shard: &PacketShard, mut packet: Packet) -> Result<(), Packet> {
+        let mut paused = false;
+
+        loop {
+            // Register the waiter before checking the shard : a pop in between is then not lost.
+            let room = shard.room.notified();
+            tokio::pin!(room);
+            room.as_mut().enable();
+
+            if !shard.is_busy(&packet.stats) {
+                match shard.try_push(packet) {
+                    Ok(()) => return Ok(()),
+                    Err(rejected) => packet = rejected, // filled by another connection in between
+                }
+            }
+            if !SERVER_IS_RUNNING.load(Ordering::Acquire) {
+                return Err(packet);
+            }
+
+            if !paused {
+                paused = true;
+                self.overflows.paused.fetch_add(1, Ordering::Relaxed);
+                packet.stats.overflows.paused.fetch_add(1, Ordering::Relaxed);
+                if CONFIG.debug_client_manager {
+                    debug!("Packet buffer is busy, reading from {} paused.", packet.source);
+                }
+            }
+            room.await;
+        }
+    }
+
+    pub fn pop(&self, shard: usize) -> Option<Packet> {
+        let shard = &self.shards[shard];
+        let packet = shard.queue.pop()?;
+
+        shard.dequeued(&packet.stats);
+        if self.policy == OverflowPolicy::PauseBusiest {
+            shard.room.notify_waiters();
+        }
+        Some(packet)
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.queue.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| shard.queue.is_empty())
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+}
+
+// Every datagram except Data are control datagrams : they are kept when the buffer is under pressure.
+// A dropped Data published with ack is retransmitted by its publisher.
+fn is_control_packet(packet: &Packet) -> bool {
+    packet.as_bytes().first()
+        .is_none_or(|code| !matches!(DatagramType::from(*code), DatagramType::Data | DatagramType::DataWithAck))
+}
 
 pub async fn init_job_system() -> prelude::Result<()> {
-    let num_cores = num_cpus::get(); // Get the number of physical cores
+    let worker_count = PACKET_BUFFER.shard_count();
+    info!("- Job system started with {} workers.", worker_count);
 
-    let mut workers: Vec<JoinHandle<()>> = Vec::with_capacity(num_cores);
+    let mut workers: Vec<JoinHandle<()>> = Vec::with_capacity(worker_count);
 
-    for _ in 0..num_cores {
-        workers.push(task::spawn_blocking(move || {
-            tokio::runtime::Runtime::new().unwrap().block_on(js_worker());
-        }));
+    for worker_id in 0..worker_count {
+        workers.push(tokio::spawn(js_worker(worker_id)));
     }
 
-    join!(async {
-        for handle in workers {
-            handle.await;
+    for handle in workers {
+        if let Err(err) = handle.await {
+            error!("A job system worker stopped unexpectedly : {}", err);
         }
-    });
+    }
 
+    info!("Job system stopped, every packet has been computed.");
+    if PACKET_BUFFER.overflows.dropped() > 0 {
+        warn!("{} packets were dropped because the packet buffer was full (newest: {}, oldest: {}, by priority: {}).",
+            PACKET_BUFFER.overflows.dropped(),
+            PACKET_BUFFER.overflows.dropped_newest.load(Ordering::Relaxed),
+            PACKET_BUFFER.overflows.dropped_oldest.load(Ordering::Relaxed),
+            PACKET_BUFFER.overflows.dropped_by_priority.load(Ordering::Relaxed));
+    }
     Ok(())
 }
 
+///
+/// push_packet is called by the receive loops of each connection.
+/// The packet is queued in the PACKET_BUFFER and the worker of its
+/// shard is woken up to compute it. With the pause_busiest policy,
+/// the caller may wait here until there is room in the buffer.
+///
+pub async fn push_packet(packet: Packet) {
+    // Once the server is stopping, only the packets already queued are computed
+    if !SERVER_IS_RUNNING.load(Ordering::Acquire) {
+        PACKET_POOL.recycle(packet);
+        return;
+    }
+    if let Some(packet) = PACKET_BUFFER.push(packet).await {
+        warn!("Packet buffer is full, packet from {} dropped.", packet.source);
+        PACKET_POOL.recycle(packet);
+    }
+}
+
+///
+/// wake_workers wake up every sleeping worker. It must be called after
+/// SERVER_IS_RUNNING is set to false so workers drain the buffer and stop.
+///
+pub fn wake_workers() {
+    for shard in &PACKET_BUFFER.shards {
+        shard.notify.notify_waiters();
+        shard.room.notify_waiters();
+    }
+}
+
 ///
 /// js_worker are started in `init_job_system` method.
-/// Each worker get packet to compute from the PACKET_BUFFER and
-/// try to empty it while the server is running.
-///
-///
-async fn js_worker() {
-    while SERVER_IS_RUNNING.load(Ordering::Acquire) {
-        // Get the first packet of the queue
-        let packet = match PACKET_BUFFER.pop() {
-            Some(packet) => packet,
-            None => {
-                // If no packet : lock the thread until there is some packet to compute
-                let &(ref lock, ref cvar) = &*WORKER_CONDVAR.clone();
-                let mut waiting = lock.lock();
-                if PACKET_BUFFER.is_empty() {
-                    // buffer is empty : wait on this line until the condvar is notified
-                    cvar.wait(&mut waiting);
-                }
-                continue;
-            }
-        };
+/// Each worker get packet to compute from its shard of the PACKET_BUFFER
+/// and try to empty it while the server is running. Once the server is
+/// stopped, the worker empty the buffer one last time and then return.
+///
+async fn js_worker(worker_id: usize) {
+    loop {
+        // Register the waiter before checking the buffer and the server state :
+        // a notification sent in between is then not lost.
+        let notified = PACKET_BUFFER.shards[worker_id].notify.notified();
+        tokio::pin!(notified);
+        notified.as_mut().enable();
 
-        // compute the packet
-        crate::handle_datagram(packet).await;
+        // compute every queued packet
+        while let Some(packet) = PACKET_BUFFER.pop(worker_id) {
+            datagram_handler::handle_datagram(&packet).await;
+            PACKET_POOL.recycle(packet);
+        }
+
+        if !SERVER_IS_RUNNING.load(Ordering::Acquire) {
+            break;
+        }
+
+        // buffer is empty : wait on this line until a packet is pushed
+        notified.await;
     }
-}
\ No newline at end of file
+
+    debug!("Job system worker {} stopped.", worker_id);
+}
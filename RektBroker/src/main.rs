@@ -18,46 +18,77 @@ use std::io::Bytes;
 use std::net::{IpAddr, SocketAddr};
 use std::ops::Deref;
 use std::str::FromStr;
-use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
-use crossbeam_queue::ArrayQueue;
 use dashmap::DashMap;
 use dashmap::mapref::one::RefMut;
 use lazy_static::lazy_static;
 use local_ip_address::local_ip;
-use parking_lot::{Condvar, Mutex};
-use quinn::{Connecting, Connection, ConnectionError, Endpoint, ServerConfig};
+use quinn::{Connecting, Connection, ConnectionError, Endpoint, EndpointConfig, ServerConfig, TokioRuntime, TransportConfig};
+use rekt_lib::datagrams::connect_requests::DtgConnectAck;
 use rustls::{Certificate, PrivateKey};
 use serde::Serialize;
+use socket2::{Domain, Protocol, Socket, Type};
 use tokio::{join, task, try_join};
 use tokio::net::UdpSocket;
 use tokio::task::JoinHandle;
 
+use crate::acl::AccessControl;
+use crate::auth::Authenticator;
 use crate::clients::client::{Client, ConnectionId, Packet};
+use crate::clients::client_manager;
 use crate::errors::Error;
+use crate::job_system::PacketBuffer;
+use crate::packet_pool::PacketPool;
 use crate::prelude::{ClientId, ClientMap, Config, Error::InitializationError, ServerSocket};
 use crate::streams::streams::RBiStream;
-
+use crate::topics::durable_log::DurableLog;
+use crate::topics::object_registry::ObjectRegistry;
+use crate::topics::retained_values::RetainedValues;
+use crate::topics::topic_history::TopicHistory;
+use crate::topics::topic_registry::TopicRegistry;
+
+mod acl;
+mod auth;
 mod config;
 mod errors;
 mod prelude;
 mod clients;
 mod streams;
 mod job_system;
+mod handlers;
+mod heartbeat_checker;
+mod packet_pool;
+mod ping_sender;
+mod shutdown;
+mod tls;
+mod topics;
+mod tests;
 
 
 lazy_static! {
     // Global config and general purpose vars
     static ref SERVER_IS_RUNNING: Arc<AtomicBool> = Arc::new(AtomicBool::new(true));
     static ref CONFIG: Config = Config::new(); // Unique reference to the config object
+    static ref ENDPOINTS: Mutex<Vec<Endpoint>> = Mutex::new(Vec::new()); // every open endpoint, closed on shutdown
 
     // Client vars
+    static ref AUTHENTICATOR: Authenticator = Authenticator::from_config(); // identities allowed to connect
+    static ref ACCESS_CONTROL: AccessControl = AccessControl::from_config(); // publish and subscribe rights of each identity
     static ref CLIENT_MAP: ClientMap = Arc::new(DashMap::default()); // store each client connection <ConnectionId, Client>
+    static ref ADMITTED_CLIENTS: AtomicUsize = AtomicUsize::new(0); // slots reserved by the handshake, released when the client is removed
 
     // Job system vars
-    static ref PACKET_BUFFER: Arc<ArrayQueue<Packet>> = Arc::new(ArrayQueue::new(CONFIG.packet_buffer_size.into()));
-    static ref WORKER_CONDVAR: Arc<(Mutex<bool>, Condvar)> = Arc::new((Mutex::new(false), Condvar::new()));
+    static ref PACKET_BUFFER: PacketBuffer = PacketBuffer::new(CONFIG.worker_count, CONFIG.packet_buffer_size.into(), CONFIG.overflow_policy, CONFIG.overflow_reserve);
+    static ref PACKET_POOL: PacketPool = PacketPool::new(CONFIG.packet_buffer_size.into(), CONFIG.packet_max_size);
+
+    // Topic vars
+    static ref TOPIC_REGISTRY: TopicRegistry = TopicRegistry::new(); // store the subscribers of each topic
+    static ref OBJECT_REGISTRY: ObjectRegistry = ObjectRegistry::new(); // store the topics and the subscribers of each object
+    static ref RETAINED_VALUES: RetainedValues = RetainedValues::new(); // store the last value of each retained topic
+    static ref TOPIC_HISTORY: TopicHistory = TopicHistory::new(); // store the last messages of each history topic
+    static ref DURABLE_LOG: DurableLog = DurableLog::new(); // write the messages of each durable topic on disk
 
 /*
     // List of client's :
@@ -66,14 +97,7 @@ lazy_static! {
     static ref CLIENTS_ADDRESSES_REF: ClientsHashMap<SocketAddr> = Arc::new(RwLock::new(HashMap::default())); // <Client ID, address> -> Used to send data
 
     // List of time reference for ping requests
-    static ref PINGS_REF: PingsHashMap = Arc::new(Mutex::new(HashMap::default())); // <Ping ID, reference time in ms>
-
-    // List of topic subscribers
-    static ref TOPICS_SUBSCRIBERS_REF: TopicsHashMap<HashSet<ClientId>> = Arc::new(RwLock::new(HashMap::default())); // <Topic ID, [Clients ID]>
-
-    // List of Objects (group of topics)
-    static ref OBJECTS_TOPICS_REF: ObjectHashMap<HashSet<TopicId>> = Arc::new(RwLock::new(HashMap::default())); // <ObjectId, [TopicId]>
-    static ref OBJECT_SUBSCRIBERS_REF: ObjectHashMap<HashSet<ClientId>>  = Arc::new(RwLock::new(HashMap::default())); // <ObjectId, [ClientId]>*/
+    static ref PINGS_REF: PingsHashMap = Arc::new(Mutex::new(HashMap::default())); // <Ping ID, reference time in ms>*/
 }
 
 #[tokio::main]
@@ -86,35 +110,58 @@ async fn main() {
     info!("Log level set to {} ...", &CONFIG.debug_level);
     info!("Check config.toml file to change the config.");
 
+    // Load the credentials and the ACL now to report any error at startup
+    lazy_static::initialize(&AUTHENTICATOR);
+    lazy_static::initialize(&ACCESS_CONTROL);
+
     // ----------------------------------------------------
     // Starting the server
     // ----------------------------------------------------
     info!("Starting the server :");
 
-    let endpoint_handle = tokio::spawn(async {
-        open_endpoint().await;
+    let mut endpoint_handle = tokio::spawn(async {
+        if let Err(err) = open_endpoint().await {
+            error!("{}", err);
+        }
     });
-    let job_system_handle = tokio::spawn(async {
+    let mut job_system_handle = tokio::spawn(async {
         job_system::init_job_system().await;
     });
-
-    let handles_results = try_join!(endpoint_handle, job_system_handle);
-
-    match handles_results {
-        Ok(_) => {}
-        Err(err) => {
-            error!("{}", err);
-            error!(">>> Server stopping!");
-            return;
+    let mut heartbeat_checker_handle = tokio::spawn(async {
+        heartbeat_checker::init_heartbeat_checker().await;
+    });
+    let mut ping_sender_handle = tokio::spawn(async {
+        ping_sender::init_ping_sender().await;
+    });
+    let mut durable_log_handle = tokio::task::spawn_blocking(topics::durable_log::run_writer);
+
+    tokio::select! {
+        handles_results = async {
+            try_join!(&mut endpoint_handle, &mut job_system_handle, &mut heartbeat_checker_handle, &mut ping_sender_handle, &mut durable_log_handle)
+        } => {
+            match handles_results {
+                Ok(_) => {}
+                Err(err) => {
+                    error!("{}", err);
+                    error!(">>> Server stopping!");
+                }
+            }
+        }
+        _ = shutdown::wait_for_signal() => {
+            shutdown::graceful_shutdown(job_system_handle, durable_log_handle).await;
         }
     }
 }
 
 fn init_quic_connection() -> Result<(ServerConfig), Error>
 {
-    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])?;
-    let key = PrivateKey(cert.serialize_private_key_der());
-    let server_config = ServerConfig::with_single_cert(vec!(Certificate(cert.serialize_der()?)), key)?;
+    let (cert_chain, key) = tls::load_server_certificate()?;
+    let mut server_config = ServerConfig::with_single_cert(cert_chain, key)?;
+
+    // The egress queues wait for room in this buffer before sending an unreliable message
+    let mut transport_config = TransportConfig::default();
+    transport_config.datagram_send_buffer_size(CONFIG.egress_datagram_buffer);
+    server_config.transport_config(Arc::new(transport_config));
 
     Ok((server_config))
 }
@@ -130,45 +177,80 @@ async fn open_endpoint() -> prelude::Result<()> {
         }
     };
 
+    if CONFIG.bind_addresses.is_empty() {
+        return Err(InitializationError("No valid bind address found in the config.".to_string()));
+    }
 
-    // Fetch the local ip address and config port to start the server
-    let addr: SocketAddr = match local_ip() {
-        Ok(ip) => {
-            info!("Server starting on {}:{} ...", ip, &CONFIG.port);
-            format!("127.0.0.1:{}", &CONFIG.port).parse()?
+    // Open one endpoint per bind address. An IPv6 wildcard alone also accept IPv4 clients (dual-stack),
+    // but it must be restricted to IPv6 when an IPv4 address is bound on the same port.
+    // An address that can't be bound (e.g. "::" with IPv6 disabled) is skipped.
+    let mut accept_handles: Vec<JoinHandle<()>> = Vec::with_capacity(CONFIG.bind_addresses.len());
+    for addr in &CONFIG.bind_addresses {
+        let v6_only = CONFIG.bind_addresses.iter()
+            .any(|other| other.is_ipv4() && other.port() == addr.port());
+
+        let bound = bind_endpoint(*addr, quic_config.clone(), v6_only)
+            .and_then(|endpoint| Ok((endpoint.local_addr()?, endpoint)));
+        let (local_addr, endpoint) = match bound {
+            Ok(bound) => bound,
+            Err(err) => {
+                error!("Failed to bind {}, address skipped : {}", addr, err);
+                continue;
+            }
+        };
+
+        if local_addr.ip().is_unspecified() {
+            // Display the local ip to make the wildcard address easier to reach
+            match local_ip() {
+                Ok(ip) => info!("Server listening on {} (local ip {}) ...", local_addr, ip),
+                Err(_) => info!("Server listening on {} ...", local_addr),
+            }
+        } else {
+            info!("Server listening on {} ...", local_addr);
         }
-        Err(err) => {
-            // can't start the server if the local ip can't be reach
-            return Err(InitializationError(format!("Failed to get local IP:\n{}", err)));
-        }
-    };
 
-    // Bind this endpoint to a UDP socket on the given server address.
-    let endpoint = Endpoint::server(quic_config, addr)?;
+        ENDPOINTS.lock().unwrap().push(endpoint.clone());
+        accept_handles.push(tokio::spawn(accept_connections(endpoint)));
+    }
 
-    // Start iterating over incoming connections.
-    while let Some(conn) = endpoint.accept().await {
-        let connection_process = handle_connection(conn);
-        tokio::spawn(async move { connection_process.await; });
+    if accept_handles.is_empty() {
+        return Err(InitializationError("None of the bind addresses could be bound.".to_string()));
+    }
+    for handle in accept_handles {
+        handle.await;
     }
 
     Ok(())
 }
 
+/**
+ * This method bind a new server endpoint to the given address.
+ *
+ * @param addr: SocketAddr, the address to bind
+ * @param quic_config: ServerConfig, the QUIC configuration of the endpoint
+ * @param v6_only: bool, only used for IPv6 addresses, refuse IPv4-mapped clients if true
+ *
+ * @return prelude::Result<Endpoint>
+ */
+fn bind_endpoint(addr: SocketAddr, quic_config: ServerConfig, v6_only: bool) -> prelude::Result<Endpoint> {
+    let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(v6_only)?;
+    }
+    socket.bind(&addr.into())?;
 
-async fn handle_datagram(packet: Packet) {
-    // TODO : Handle packet according the source and the datagram
-
-    // 1 - fetch a ref ot the client :
-    let client = match CLIENT_MAP.get_mut(&packet.source) {
-        None => { return; }
-        Some(entry) => { entry }
-    };
-
-    // 2 - build the datagram struct + respond to it
-
+    match Endpoint::new(EndpointConfig::default(), Some(quic_config), socket.into(), Arc::new(TokioRuntime)) {
+        Ok(endpoint) => Ok(endpoint),
+        Err(err) => Err(InitializationError(format!("Failed to open the endpoint on {} : {}", addr, err))),
+    }
+}
 
-    client.unreliable_stream.stream.send_datagram(bytes::Bytes::from("Un Packet unreliable"));
+async fn accept_connections(endpoint: Endpoint) {
+    // Start iterating over incoming connections.
+    while let Some(conn) = endpoint.accept().await {
+        let connection_process = handle_connection(conn);
+        tokio::spawn(async move { connection_process.await; });
+    }
 }
 
 
@@ -184,17 +266,38 @@ async fn handle_connection(pending_connection: Connecting) -> prelude::Result<()
         }
     };
 
-    // Open the bidirectional stream to this client
+    // The connection stay pending until the client send its connect request
     let connection_id = ConnectionId::from_connection(&connection);
-    info!("New connection received from {}\nOpening a bi-stream to this connection", connection_id);
-    let (mut sender, mut receiver) = connection
-        .open_bi()
-        .await?;
+    info!("New connection received from {}, waiting for its connect request", connection_id);
+    let identity = match client_manager::handshake(connection_id, &connection).await {
+        Some(identity) => identity,
+        None => return Ok(()),
+    };
 
-    // Store the client to the static hashmap.
-    let client = Client::new(connection_id, connection, RBiStream { sender, receiver });
-    CLIENT_MAP.entry(connection_id).insert(client);
+    // Open the bidirectional stream to this client, the slot reserved by the handshake is released on failure
+    let (mut sender, mut receiver) = match connection.open_bi().await {
+        Ok(stream) => stream,
+        Err(err) => {
+            client_manager::release_slot();
+            return Err(err.into());
+        }
+    };
+
+    // Store the client to the static hashmap and acknowledge the connection.
+    let client = Client::new(connection_id, connection.clone(), RBiStream { sender, receiver }, identity);
+    let stats = client.stats.clone();
+    let connect_ack = DtgConnectAck::new(client.id, CONFIG.heart_beat_period).as_bytes();
+    info!("Client {} connected with the id {} as {}", connection_id, client.id, client.identity);
+    if let Some(replaced) = CLIENT_MAP.insert(connection_id, client) {
+        client_manager::replace_client(connection_id, replaced);
+    }
+    if let Some(Err(err)) = CLIENT_MAP.get(&connection_id).map(|client| client.send_datagram(connect_ack)) {
+        client_manager::remove_client(connection_id, connection.stable_id());
+        return Err(err);
+    }
 
+    // Read everything the client send until the connection is closed
+    client_manager::listen_connection(connection_id, connection, stats).await;
 
     Ok(())
 }
@@ -0,0 +1,78 @@
+// This document contain the buffer pool of the packets. Buffers used to read
+// the client streams are taken from the pool, frozen into reference-counted
+// Bytes for the job system and given back to the pool once the packet has been
+// computed, so the broker doesn't allocate a new buffer for each packet.
+
+use std::sync::Arc;
+
+use bytes::BytesMut;
+use crossbeam_queue::ArrayQueue;
+
+use crate::clients::client::{ClientStats, ConnectionId, Packet};
+
+// Initial capacity of a new buffer, enough for most datagrams
+const DEFAULT_BUFFER_CAPACITY: usize = 2048;
+
+pub struct PacketPool {
+    buffers: ArrayQueue<BytesMut>,
+    max_buffer_capacity: usize, // bigger buffers are freed instead of being kept in the pool
+}
+
+impl PacketPool {
+    pub fn new(pool_size: usize, max_buffer_capacity: usize) -> PacketPool {
+        PacketPool {
+            buffers: ArrayQueue::new(pool_size.max(1)),
+            max_buffer_capacity: max_buffer_capacity.max(DEFAULT_BUFFER_CAPACITY),
+        }
+    }
+
+    /**
+     * This method return an empty buffer, from the pool if one
+     * is available or a newly allocated one otherwise.
+     *
+     * @return BytesMut
+     */
+    pub fn acquire(&self) -> BytesMut {
+        self.buffers.pop()
+            .unwrap_or_else(|| BytesMut::with_capacity(DEFAULT_BUFFER_CAPACITY))
+    }
+
+    /**
+     * This method give back a buffer to the pool.
+     *
+     * @param buffer: BytesMut, the buffer to reuse
+     */
+    pub fn release(&self, mut buffer: BytesMut) {
+        if buffer.capacity() > self.max_buffer_capacity {
+            return;
+        }
+        buffer.clear();
+        // If the pool is full the buffer is simply freed
+        let _ = self.buffers.push(buffer);
+    }
+
+    /**
+     * This method build a packet from a filled buffer of the pool.
+     *
+     * @param source: ConnectionId, the client that sent the datagram
+     * @param buffer: BytesMut, the datagram
+     * @param stats: Arc<ClientStats>, the stats of the client
+     *
+     * @return Packet
+     */
+    pub fn packet(&self, source: ConnectionId, buffer: BytesMut, stats: Arc<ClientStats>) -> Packet {
+        Packet::new(source, buffer.freeze(), stats)
+    }
+
+    /**
+     * This method recycle the buffer of a computed packet. The buffer is only
+     * reused if no other reference to the datagram still exist.
+     *
+     * @param packet: Packet, the computed packet
+     */
+    pub fn recycle(&self, packet: Packet) {
+        if let Ok(buffer) = packet.datagram.try_into_mut() {
+            self.release(buffer);
+        }
+    }
+}
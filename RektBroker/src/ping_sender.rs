@@ -0,0 +1,56 @@
+// This document contain the ping sender. Every ping period a DtgPing is sent to
+// each client, the DtgPong answers are matched by the datagram handler and give
+// the RTT and jitter of the client (see clients/latency.rs). The depths of the
+// egress queues of each client are logged at the same period when not empty.
+
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use rekt_lib::datagrams::latency_requests::DtgPing;
+use tokio::time::MissedTickBehavior;
+
+use crate::{CLIENT_MAP, CONFIG, prelude, SERVER_IS_RUNNING};
+
+pub async fn init_ping_sender() -> prelude::Result<()> {
+    let period = Duration::from_secs(CONFIG.ping_period.max(1).into());
+    info!("- Ping sender started (period {:?}).", period);
+
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    while SERVER_IS_RUNNING.load(Ordering::Acquire) {
+        interval.tick().await;
+        ping_clients();
+    }
+
+    debug!("Ping sender stopped.");
+    Ok(())
+}
+
+/**
+ * This method send a new ping to every connected client,
+ * and log the depths of its egress queues.
+ */
+fn ping_clients() {
+    for client in CLIENT_MAP.iter() {
+        let ping_id = client.stats.latency.start_ping();
+
+        if CONFIG.debug_ping_sender {
+            trace!("Ping {} sent to {}", ping_id, client.connection_id);
+        }
+        if let Err(err) = client.send_datagram(DtgPing::new(ping_id).as_bytes()) {
+            warn!("Failed to send a ping to {} : {}", client.connection_id, err);
+        }
+
+        if CONFIG.debug_egress {
+            let [high, normal, low] = client.egress.depths();
+            let reliable = client.egress.reliable_backlog();
+            if high + normal + low + reliable > 0 {
+                let shed = &client.egress.shed;
+                debug!("Egress of {} : {} high, {} normal, {} low priority messages waiting ({}/{}/{} shed), {} reliable not written yet",
+                    client.connection_id, high, normal, low,
+                    shed[0].load(Ordering::Relaxed), shed[1].load(Ordering::Relaxed), shed[2].load(Ordering::Relaxed), reliable);
+            }
+        }
+    }
+}
@@ -0,0 +1,96 @@
+// This document contain the graceful shutdown of the broker. On SIGINT or
+// SIGTERM the broker stop accepting connections, send a DtgShutdown(Shutdown)
+// to every client and let the job system compute the queued packets during
+// the grace period. The durable log is then synced to the disk, and the
+// endpoints are closed with the shutdown code so clients know the broker
+// stopped on purpose.
+
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use quinn::VarInt;
+use rekt_lib::datagrams::shutdown_request::DtgShutdown;
+use rekt_lib::enums::end_connection_reason::EndConnexionReason;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+
+use crate::{CLIENT_MAP, CONFIG, DURABLE_LOG, ENDPOINTS, job_system, PACKET_BUFFER, SERVER_IS_RUNNING};
+
+/**
+ * This method return once the broker received SIGINT (Ctrl-C) or SIGTERM.
+ */
+pub async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+                return;
+            }
+            Err(err) => warn!("Failed to listen for SIGTERM, only Ctrl-C will stop the broker : {}", err),
+        }
+    }
+
+    if let Err(err) = tokio::signal::ctrl_c().await {
+        error!("Failed to listen for Ctrl-C : {}", err);
+        std::future::pending::<()>().await;
+    }
+}
+
+/**
+ * This method stop the broker. It return once every endpoint is
+ * closed or when the grace period is over.
+ *
+ * @param job_system: JoinHandle<()>, the task of the job system, used to know when the buffer is drained
+ * @param durable_log: JoinHandle<()>, the writer thread of the durable log
+ */
+pub async fn graceful_shutdown(job_system: JoinHandle<()>, durable_log: JoinHandle<()>) {
+    let grace_period = Duration::from_secs(CONFIG.shutdown_grace_period.into());
+    let shutdown = DtgShutdown::new(EndConnexionReason::Shutdown).as_bytes();
+    let endpoints = ENDPOINTS.lock().unwrap().clone();
+    info!("Shutdown requested, stopping the server ({:?} grace period) ...", grace_period);
+
+    // 1 - Stop accepting new connections and new packets
+    SERVER_IS_RUNNING.store(false, Ordering::Release);
+    for endpoint in &endpoints {
+        endpoint.set_server_config(None);
+    }
+
+    // 2 - Notify every client
+    for client in CLIENT_MAP.iter() {
+        if let Err(err) = client.send_datagram(shutdown.clone()) {
+            debug!("Failed to send the shutdown to {} : {}", client.connection_id, err);
+        }
+    }
+    info!("- {} clients notified.", CLIENT_MAP.len());
+
+    // 3 - Wake the sleeping workers so they drain their shard and stop
+    job_system::wake_workers();
+    match timeout(grace_period, job_system).await {
+        Ok(_) => info!("- Packet buffer drained."),
+        Err(_) => warn!("- Grace period over, {} queued packets are dropped.", PACKET_BUFFER.len()),
+    }
+
+    // 4 - Write the last durable messages on disk
+    DURABLE_LOG.stop();
+    if timeout(grace_period, durable_log).await.is_err() {
+        warn!("- The durable log didn't stop in time, its last messages may be lost.");
+    }
+
+    // 5 - Close every connection with the shutdown code and wait for the peers to acknowledge it
+    for endpoint in &endpoints {
+        endpoint.close(VarInt::from(u8::from(EndConnexionReason::Shutdown)), &shutdown);
+    }
+    for endpoint in &endpoints {
+        if timeout(grace_period, endpoint.wait_idle()).await.is_err() {
+            warn!("- Some connections of {:?} didn't close in time.", endpoint.local_addr());
+        }
+    }
+
+    info!(">>> Server stopped.");
+}
@@ -0,0 +1,4 @@
+#[cfg(test)]
+mod registry_test;
+#[cfg(test)]
+mod unit_test;
\ No newline at end of file
@@ -0,0 +1,116 @@
+#![allow(non_snake_case)]
+
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Arc;
+use std::thread;
+
+use crate::acl::{Permissions, TopicPattern};
+use crate::clients::client::ConnectionId;
+use crate::topics::object_registry::ObjectRegistry;
+use crate::topics::topic_registry::TopicRegistry;
+use crate::TOPIC_REGISTRY;
+
+// The topic registry is global : each test use its own clients and topics
+fn test_client(port: u16) -> ConnectionId {
+    ConnectionId::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port)
+}
+
+// -------------------------------------------------------
+//   Topic registry
+// -------------------------------------------------------
+#[test]
+fn test_TopicRegistry_direct_and_object_refs() {
+    let registry = TopicRegistry::new();
+    let client = test_client(37001);
+
+    registry.subscribe_from_object(1, client);
+    assert!(registry.subscribe(1, client, None));
+    assert!(!registry.subscribe(1, client, None));
+
+    // Still subscribed through the object
+    assert!(registry.unsubscribe(1, client));
+    assert!(registry.is_subscribed(1, client));
+    assert!(!registry.unsubscribe(1, client));
+
+    registry.unsubscribe_from_object(1, client);
+    assert!(!registry.is_subscribed(1, client));
+    assert_eq!(registry.topic_count(), 0);
+    assert_eq!(registry.remove_client(client), 0);
+}
+
+#[test]
+fn test_TopicRegistry_concurrent_updates() {
+    let registry = Arc::new(TopicRegistry::new());
+    let client = test_client(37002);
+
+    // Direct and object subscriptions of the same client and topic changed from several threads
+    let handles: Vec<_> = (0..4).map(|thread_id| {
+        let registry = registry.clone();
+        thread::spawn(move || {
+            for _ in 0..2000 {
+                if thread_id % 2 == 0 {
+                    registry.subscribe(2, client, None);
+                    registry.unsubscribe(2, client);
+                } else {
+                    registry.subscribe_from_object(2, client);
+                    registry.unsubscribe_from_object(2, client);
+                }
+            }
+        })
+    }).collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    // Every reference was removed : both maps must be empty
+    assert!(!registry.is_subscribed(2, client));
+    assert_eq!(registry.topic_count(), 0);
+    assert_eq!(registry.remove_client(client), 0);
+
+    registry.subscribe(3, client, None);
+    registry.subscribe_from_object(4, client);
+    assert_eq!(registry.remove_client(client), 2);
+    assert_eq!(registry.topic_count(), 0);
+}
+
+// -------------------------------------------------------
+//   Object registry
+// -------------------------------------------------------
+#[test]
+fn test_ObjectRegistry_subscribe_denied_topic() {
+    let registry = ObjectRegistry::new();
+    let client = test_client(43001);
+    let permissions = Permissions::new(Vec::new(), vec![TopicPattern::Range(43006, 43006)]);
+
+    let object_id = registry.create(1, HashSet::from([43005, 43006])).unwrap();
+    assert!(registry.subscribe(object_id, client, &permissions).is_err());
+    assert!(!TOPIC_REGISTRY.is_subscribed(43005, client));
+    assert!(!TOPIC_REGISTRY.is_subscribed(43006, client));
+    assert!(registry.unsubscribe(object_id, client).is_err());
+}
+
+#[test]
+fn test_ObjectRegistry_update_skip_denied_topic() {
+    let registry = ObjectRegistry::new();
+    let client = test_client(43002);
+    let permissions = Permissions::new(Vec::new(), vec![TopicPattern::Range(43016, 43016)]);
+
+    let object_id = registry.create(2, HashSet::from([43016])).unwrap();
+    assert!(registry.subscribe(object_id, client, &permissions).is_ok());
+    assert!(TOPIC_REGISTRY.is_subscribed(43016, client));
+
+    // The denied topic added by the update is not given to the subscriber
+    registry.update(object_id, HashSet::from([43015, 43016])).unwrap();
+    assert!(!TOPIC_REGISTRY.is_subscribed(43015, client));
+    assert!(TOPIC_REGISTRY.is_subscribed(43016, client));
+
+    // Removing the denied topic doesn't touch the direct subscription to it
+    assert!(TOPIC_REGISTRY.subscribe(43015, client, None));
+    registry.update(object_id, HashSet::from([43016])).unwrap();
+    assert!(TOPIC_REGISTRY.is_subscribed(43015, client));
+
+    registry.delete(object_id).unwrap();
+    assert!(!TOPIC_REGISTRY.is_subscribed(43016, client));
+    assert_eq!(TOPIC_REGISTRY.remove_client(client), 1);
+}
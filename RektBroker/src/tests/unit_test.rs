@@ -0,0 +1,386 @@
+#![allow(non_snake_case)]
+
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use rekt_lib::datagrams::data_request::DtgData;
+use rekt_lib::enums::datagram_type::DatagramType;
+use rekt_lib::enums::delivery_mode::DeliveryMode;
+
+use crate::CONFIG;
+use crate::acl::TopicPattern;
+use crate::clients::client::{ClientStats, ConnectionId, Packet};
+use crate::clients::egress::{EgressQueue, Next, TopicPriority};
+use crate::clients::rate_limiter::{RateLimit, RateLimiter, RateVerdict};
+use crate::job_system::{OverflowPolicy, PacketBuffer};
+use crate::topics::durable_log::{count_records, recover_segment, SegmentReader};
+
+fn message(len: usize) -> Bytes {
+    Bytes::from(vec![0u8; len])
+}
+
+// -------------------------------------------------------
+//   Egress queues
+// -------------------------------------------------------
+#[test]
+fn test_EgressQueue_pop_highest_first() {
+    let egress = EgressQueue::default();
+    egress.push(message(1), DeliveryMode::Unreliable, TopicPriority::Low);
+    egress.push(message(2), DeliveryMode::Unreliable, TopicPriority::Normal);
+    egress.push(message(3), DeliveryMode::Unreliable, TopicPriority::High);
+    assert_eq!(egress.depths(), [1, 1, 1]);
+
+    let space = CONFIG.egress_datagram_buffer;
+    assert_eq!(egress.pop(space), Next::Message(message(3), DeliveryMode::Unreliable, TopicPriority::High));
+    assert_eq!(egress.pop(space), Next::Message(message(2), DeliveryMode::Unreliable, TopicPriority::Normal));
+    assert_eq!(egress.pop(space), Next::Message(message(1), DeliveryMode::Unreliable, TopicPriority::Low));
+    assert_eq!(egress.pop(space), Next::Empty);
+}
+
+#[test]
+fn test_EgressQueue_push_shed_lowest() {
+    let egress = EgressQueue::default();
+    for _ in 0..CONFIG.egress_queue_size {
+        egress.push(message(1), DeliveryMode::Unreliable, TopicPriority::Low);
+    }
+    // Reliable messages don't count in the unreliable limit and are never shed
+    egress.push(message(1), DeliveryMode::ReliableOrdered, TopicPriority::Low);
+    assert_eq!(egress.shed_count(), 0);
+
+    // The oldest low priority message make room for the high priority one
+    egress.push(message(2), DeliveryMode::Unreliable, TopicPriority::High);
+    assert_eq!(egress.depths(), [1, 0, CONFIG.egress_queue_size]);
+    assert_eq!(egress.shed_count(), 1);
+    assert_eq!(egress.shed[2].load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn test_EgressQueue_push_shed_new_when_lowest() {
+    let egress = EgressQueue::default();
+    for _ in 0..CONFIG.egress_queue_size {
+        egress.push(message(1), DeliveryMode::Unreliable, TopicPriority::High);
+    }
+
+    // Nothing waiting has a lower priority : the new message is shed
+    egress.push(message(2), DeliveryMode::Unreliable, TopicPriority::Low);
+    assert_eq!(egress.depths(), [CONFIG.egress_queue_size, 0, 0]);
+    assert_eq!(egress.shed[2].load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn test_EgressQueue_pop_reliable_behind_congested() {
+    let egress = EgressQueue::default();
+    egress.push(message(100), DeliveryMode::Unreliable, TopicPriority::High);
+    egress.push(message(101), DeliveryMode::Unreliable, TopicPriority::Normal);
+    egress.push(message(102), DeliveryMode::ReliableOrdered, TopicPriority::Normal);
+    egress.push(message(103), DeliveryMode::Unreliable, TopicPriority::Low);
+
+    // The datagram send buffer has room for 10 bytes only : the reliable message is not blocked
+    assert_eq!(egress.pop(10), Next::Message(message(102), DeliveryMode::ReliableOrdered, TopicPriority::Normal));
+    assert_eq!(egress.pop(10), Next::Congested(90));
+    assert_eq!(egress.reliable_backlog(), 1);
+
+    let space = CONFIG.egress_datagram_buffer;
+    assert_eq!(egress.pop(space), Next::Message(message(100), DeliveryMode::Unreliable, TopicPriority::High));
+}
+
+#[test]
+fn test_EgressQueue_push_reliable_overflow() {
+    let egress = EgressQueue::default();
+    for _ in 0..CONFIG.egress_reliable_queue_size {
+        egress.push(message(1), DeliveryMode::ReliableUnordered, TopicPriority::Normal);
+    }
+    assert!(!egress.is_overflowed());
+
+    egress.push(message(1), DeliveryMode::ReliableUnordered, TopicPriority::Normal);
+    assert!(egress.is_overflowed());
+    assert_eq!(egress.reliable_backlog(), CONFIG.egress_reliable_queue_size);
+    assert_eq!(egress.depths(), [0, CONFIG.egress_reliable_queue_size, 0]);
+}
+
+// -------------------------------------------------------
+//   Rate limiter
+// -------------------------------------------------------
+fn seconds(start: Instant, seconds: u64) -> Instant {
+    start + Duration::from_secs(seconds)
+}
+
+#[test]
+fn test_RateLimiter_unlimited() {
+    let limiter = RateLimiter::new(RateLimit::default());
+    for _ in 0..1000 {
+        assert_eq!(limiter.check(usize::MAX), RateVerdict::Accept);
+    }
+}
+
+#[test]
+fn test_RateLimiter_check_messages() {
+    let limiter = RateLimiter::new(RateLimit { messages_per_second: 2, bytes_per_second: 0 });
+    let start = Instant::now();
+
+    assert_eq!(limiter.check_at(10, start), RateVerdict::Accept);
+    assert_eq!(limiter.check_at(10, start), RateVerdict::Accept);
+    assert_eq!(limiter.check_at(10, start), RateVerdict::Drop);
+    assert_eq!(limiter.dropped.load(Ordering::Relaxed), 1);
+
+    // Half a second refill one message
+    assert_eq!(limiter.check_at(10, start + Duration::from_millis(500)), RateVerdict::Accept);
+    assert_eq!(limiter.check_at(10, start + Duration::from_millis(500)), RateVerdict::Drop);
+}
+
+#[test]
+fn test_RateLimiter_check_bucket_debt() {
+    let limiter = RateLimiter::new(RateLimit { messages_per_second: 0, bytes_per_second: 1000 });
+    let start = Instant::now();
+
+    // Bigger than the bucket : accepted since it is full, the bucket then owe 4000 bytes
+    assert_eq!(limiter.check_at(5000, start), RateVerdict::Accept);
+    assert_eq!(limiter.check_at(1, seconds(start, 1)), RateVerdict::Drop);
+    assert_eq!(limiter.check_at(1, seconds(start, 3)), RateVerdict::Drop);
+    // The debt is paid after 4 seconds
+    assert_eq!(limiter.check_at(1, seconds(start, 4) + Duration::from_millis(100)), RateVerdict::Accept);
+}
+
+#[test]
+fn test_RateLimiter_check_strikes() {
+    let max_strikes = CONFIG.rate_limit_max_strikes as u64;
+    assert!(max_strikes > 1, "the test need max_strikes > 1 in config.toml");
+    let limiter = RateLimiter::new(RateLimit { messages_per_second: 1, bytes_per_second: 0 });
+    let start = Instant::now();
+
+    // One drop per second : the client is disconnected on the last strike
+    for second in 0..max_strikes {
+        assert_eq!(limiter.check_at(1, seconds(start, second)), RateVerdict::Accept);
+        let expected = if second + 1 == max_strikes { RateVerdict::Disconnect } else { RateVerdict::Drop };
+        assert_eq!(limiter.check_at(1, seconds(start, second)), expected);
+    }
+}
+
+#[test]
+fn test_RateLimiter_check_strike_reset() {
+    let max_strikes = CONFIG.rate_limit_max_strikes as u64;
+    assert!(max_strikes > 1, "the test need max_strikes > 1 in config.toml");
+    let limiter = RateLimiter::new(RateLimit { messages_per_second: 1, bytes_per_second: 0 });
+    let start = Instant::now();
+
+    for second in 0..max_strikes - 1 {
+        limiter.check_at(1, seconds(start, second));
+        assert_eq!(limiter.check_at(1, seconds(start, second)), RateVerdict::Drop);
+    }
+
+    // A whole second without drop end the strikes series
+    let clean = max_strikes - 1;
+    assert_eq!(limiter.check_at(1, seconds(start, clean)), RateVerdict::Accept);
+    assert_eq!(limiter.check_at(1, seconds(start, clean + 1)), RateVerdict::Accept);
+    assert_eq!(limiter.check_at(1, seconds(start, clean + 1)), RateVerdict::Drop);
+
+    // So does a silence of two seconds
+    for second in clean + 2..clean + max_strikes {
+        limiter.check_at(1, seconds(start, second));
+        assert_eq!(limiter.check_at(1, seconds(start, second)), RateVerdict::Drop);
+    }
+    let silence = clean + max_strikes + 2;
+    limiter.check_at(1, seconds(start, silence));
+    assert_eq!(limiter.check_at(1, seconds(start, silence)), RateVerdict::Drop);
+}
+
+// -------------------------------------------------------
+//   Packet buffer
+// -------------------------------------------------------
+// A packet of one byte : its datagram type, followed by its index to tell the packets apart
+fn packet(datagram_type: DatagramType, index: u8, stats: &Arc<ClientStats>) -> Packet {
+    let source = ConnectionId::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 44000);
+    Packet::new(source, Bytes::from(vec![u8::from(datagram_type), index]), stats.clone())
+}
+
+fn index_of(packet: Option<Packet>) -> Option<u8> {
+    packet.map(|packet| packet.as_bytes()[1])
+}
+
+#[tokio::test]
+async fn test_PacketBuffer_push_drop_newest() {
+    let buffer = PacketBuffer::new(1, 2, OverflowPolicy::DropNewest, 0);
+    let stats = Arc::new(ClientStats::new(RateLimit::default()));
+
+    assert!(buffer.push(packet(DatagramType::Data, 1, &stats)).await.is_none());
+    assert!(buffer.push(packet(DatagramType::Data, 2, &stats)).await.is_none());
+    assert_eq!(index_of(buffer.push(packet(DatagramType::Data, 3, &stats)).await), Some(3));
+    assert_eq!(buffer.overflows.dropped_newest.load(Ordering::Relaxed), 1);
+    assert_eq!(stats.overflows.dropped_newest.load(Ordering::Relaxed), 1);
+    assert_eq!(stats.queued_packets.load(Ordering::Relaxed), 2);
+
+    assert_eq!(index_of(buffer.pop(0)), Some(1));
+    assert_eq!(index_of(buffer.pop(0)), Some(2));
+    assert!(buffer.is_empty());
+    assert_eq!(stats.queued_packets.load(Ordering::Relaxed), 0);
+}
+
+#[tokio::test]
+async fn test_PacketBuffer_push_drop_oldest() {
+    let buffer = PacketBuffer::new(1, 2, OverflowPolicy::DropOldest, 0);
+    let stats = Arc::new(ClientStats::new(RateLimit::default()));
+
+    assert!(buffer.push(packet(DatagramType::Data, 1, &stats)).await.is_none());
+    assert!(buffer.push(packet(DatagramType::Data, 2, &stats)).await.is_none());
+    assert_eq!(index_of(buffer.push(packet(DatagramType::Data, 3, &stats)).await), Some(1));
+    assert_eq!(buffer.overflows.dropped_oldest.load(Ordering::Relaxed), 1);
+    assert_eq!(stats.overflows.dropped_oldest.load(Ordering::Relaxed), 1);
+    assert_eq!(stats.queued_packets.load(Ordering::Relaxed), 2);
+
+    assert_eq!(index_of(buffer.pop(0)), Some(2));
+    assert_eq!(index_of(buffer.pop(0)), Some(3));
+    assert!(buffer.is_empty());
+}
+
+#[tokio::test]
+async fn test_PacketBuffer_push_drop_by_priority() {
+    // 20% of the 10 packets are kept for control packets
+    let buffer = PacketBuffer::new(1, 10, OverflowPolicy::DropByPriority, 20);
+    let stats = Arc::new(ClientStats::new(RateLimit::default()));
+
+    for index in 0..8 {
+        assert!(buffer.push(packet(DatagramType::Data, index, &stats)).await.is_none());
+    }
+    assert_eq!(index_of(buffer.push(packet(DatagramType::Data, 8, &stats)).await), Some(8));
+    assert_eq!(index_of(buffer.push(packet(DatagramType::DataWithAck, 9, &stats)).await), Some(9));
+    assert!(buffer.push(packet(DatagramType::TopicRequest, 10, &stats)).await.is_none());
+    assert!(buffer.push(packet(DatagramType::Heartbeat, 11, &stats)).await.is_none());
+    // Control packets are still dropped once the shard is full
+    assert_eq!(index_of(buffer.push(packet(DatagramType::Heartbeat, 12, &stats)).await), Some(12));
+
+    assert_eq!(buffer.overflows.dropped_by_priority.load(Ordering::Relaxed), 3);
+    assert_eq!(stats.overflows.dropped_by_priority.load(Ordering::Relaxed), 3);
+    assert_eq!(buffer.len(), 10);
+}
+
+#[tokio::test]
+async fn test_PacketBuffer_push_pause_busiest() {
+    let buffer = Arc::new(PacketBuffer::new(1, 2, OverflowPolicy::PauseBusiest, 0));
+    let stats = Arc::new(ClientStats::new(RateLimit::default()));
+
+    assert!(buffer.push(packet(DatagramType::Data, 1, &stats)).await.is_none());
+    assert!(buffer.push(packet(DatagramType::Data, 2, &stats)).await.is_none());
+
+    // The shard is full : the third push wait for room instead of dropping
+    let pending = packet(DatagramType::Data, 3, &stats);
+    let push = tokio::spawn({
+        let buffer = buffer.clone();
+        async move { buffer.push(pending).await.is_none() }
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!push.is_finished());
+    assert_eq!(buffer.overflows.paused.load(Ordering::Relaxed), 1);
+    assert_eq!(stats.overflows.paused.load(Ordering::Relaxed), 1);
+
+    assert_eq!(index_of(buffer.pop(0)), Some(1));
+    assert!(push.await.unwrap());
+    assert_eq!(index_of(buffer.pop(0)), Some(2));
+    assert_eq!(index_of(buffer.pop(0)), Some(3));
+    assert_eq!(buffer.overflows.dropped(), 0);
+}
+
+// -------------------------------------------------------
+//   Topic patterns
+// -------------------------------------------------------
+#[test]
+fn test_TopicPattern_parse() {
+    assert_eq!(TopicPattern::parse("*"), Some(TopicPattern::Any));
+    assert_eq!(TopicPattern::parse("42"), Some(TopicPattern::Range(42, 42)));
+    assert_eq!(TopicPattern::parse("100-199"), Some(TopicPattern::Range(100, 199)));
+    assert_eq!(TopicPattern::parse(" 100 - 199 "), Some(TopicPattern::Range(100, 199)));
+    assert_eq!(TopicPattern::parse("7-7"), Some(TopicPattern::Range(7, 7)));
+}
+
+#[test]
+fn test_TopicPattern_parse_open_range() {
+    let pattern = TopicPattern::parse("1000-*").unwrap();
+    assert_eq!(pattern, TopicPattern::Range(1000, u64::MAX));
+    assert!(!pattern.matches(999));
+    assert!(pattern.matches(1000));
+    assert!(pattern.matches(u64::MAX));
+}
+
+#[test]
+fn test_TopicPattern_parse_invalid() {
+    // A reversed range would match nothing : it is refused
+    assert_eq!(TopicPattern::parse("199-100"), None);
+    assert_eq!(TopicPattern::parse("*-1000"), None);
+    assert_eq!(TopicPattern::parse(""), None);
+    assert_eq!(TopicPattern::parse("abc"), None);
+    assert_eq!(TopicPattern::parse("-5"), None);
+    assert_eq!(TopicPattern::parse("5-"), None);
+}
+
+#[test]
+fn test_TopicPattern_matches() {
+    let pattern = TopicPattern::parse("100-199").unwrap();
+    assert!(!pattern.matches(99));
+    assert!(pattern.matches(100));
+    assert!(pattern.matches(199));
+    assert!(!pattern.matches(200));
+    assert!(TopicPattern::Any.matches(0));
+}
+
+// -------------------------------------------------------
+//   Durable log
+// -------------------------------------------------------
+// Three messages as written in a segment, with the size of each one
+fn segment_content() -> (Vec<u8>, Vec<usize>) {
+    let records: Vec<Vec<u8>> = (0..3u32)
+        .map(|sequence_number| DtgData::new(sequence_number, 42, vec![7; 10 * sequence_number as usize]).as_bytes())
+        .collect();
+    let sizes = records.iter().map(Vec::len).collect();
+    (records.concat(), sizes)
+}
+
+#[test]
+fn test_count_records() {
+    let (content, sizes) = segment_content();
+    assert_eq!(count_records(&content), (3, content.len()));
+    assert_eq!(count_records(&[]), (0, 0));
+
+    // An incomplete message, or anything but a DtgData, end the valid part of the segment
+    assert_eq!(count_records(&content[..content.len() - 1]), (2, sizes[0] + sizes[1]));
+    assert_eq!(count_records(&content[..sizes[0] + 3]), (1, sizes[0]));
+    let mut corrupted = content.clone();
+    corrupted[sizes[0]] = u8::from(DatagramType::Heartbeat);
+    assert_eq!(count_records(&corrupted), (1, sizes[0]));
+}
+
+#[test]
+fn test_recover_segment_truncate() {
+    let (content, sizes) = segment_content();
+    let path = std::env::temp_dir().join(format!("rekt_broker_test_{}.log", std::process::id()));
+
+    std::fs::write(&path, &content[..content.len() - 4]).unwrap();
+    assert_eq!(recover_segment(&path).unwrap(), (2, (sizes[2] - 4) as u64));
+    assert_eq!(std::fs::read(&path).unwrap(), &content[..sizes[0] + sizes[1]]);
+
+    // A complete segment is left as is
+    assert_eq!(recover_segment(&path).unwrap(), (2, 0));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_SegmentReader_read_chunk() {
+    let (content, sizes) = segment_content();
+    let path = std::env::temp_dir().join(format!("rekt_broker_test_reader_{}.log", std::process::id()));
+    std::fs::write(&path, &content[..content.len() - 4]).unwrap();
+
+    // The messages are read one at a time with a small chunk, and the incomplete one is left
+    let mut segment = SegmentReader::open(&path, None).unwrap().unwrap();
+    assert_eq!(segment.read_chunk(1).unwrap(), (1, Bytes::copy_from_slice(&content[..sizes[0]])));
+    assert_eq!(segment.read_chunk(1).unwrap(), (1, Bytes::copy_from_slice(&content[sizes[0]..sizes[0] + sizes[1]])));
+    assert_eq!(segment.read_chunk(1).unwrap(), (0, Bytes::new()));
+
+    // A resume skip the messages before its sequence number
+    std::fs::write(&path, &content).unwrap();
+    let mut segment = SegmentReader::open(&path, Some(1)).unwrap().unwrap();
+    assert_eq!(segment.read_chunk(usize::MAX).unwrap(), (2, Bytes::copy_from_slice(&content[sizes[0]..])));
+
+    std::fs::remove_file(&path).unwrap();
+    assert!(SegmentReader::open(&path, None).unwrap().is_none());
+}
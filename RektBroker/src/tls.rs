@@ -0,0 +1,124 @@
+// This document contain the TLS certificate management of the broker. The
+// certificate chain and its private key are read from the PEM files set in the
+// [tls] table of the config. If none are set, a self-signed certificate is
+// generated on the first start and saved to disk: it is then reused so its
+// fingerprint stay the same across restarts and clients can pin it.
+
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Write};
+use std::path::Path;
+
+use rustls::{Certificate, PrivateKey};
+use rustls_pemfile::Item;
+use sha2::{Digest, Sha256};
+
+use crate::CONFIG;
+use crate::prelude::{Error, Result};
+
+/**
+ * This method return the certificate chain and the private key
+ * used by the QUIC endpoint according to the config.
+ *
+ * @return Result<(Vec<Certificate>, PrivateKey)>
+ */
+pub fn load_server_certificate() -> Result<(Vec<Certificate>, PrivateKey)> {
+    let (cert_chain, key) = match (&CONFIG.tls_certificate_chain, &CONFIG.tls_private_key) {
+        (Some(cert_path), Some(key_path)) => {
+            info!("- Loading TLS certificate from {} ...", cert_path);
+            (read_certificate_chain(Path::new(cert_path))?, read_private_key(Path::new(key_path))?)
+        }
+        (None, None) => load_or_generate_self_signed()?,
+        _ => {
+            return Err(Error::Tls("certificate_chain and private_key must be set together in the tls table.".to_string()));
+        }
+    };
+
+    info!("- TLS certificate SHA-256 fingerprint : {}", fingerprint(&cert_chain[0]));
+    Ok((cert_chain, key))
+}
+
+/**
+ * This method read the self-signed certificate from the disk, or generate it
+ * and save it if it doesn't exist yet.
+ *
+ * @return Result<(Vec<Certificate>, PrivateKey)>
+ */
+fn load_or_generate_self_signed() -> Result<(Vec<Certificate>, PrivateKey)> {
+    let cert_path = Path::new(&CONFIG.tls_self_signed_certificate);
+    let key_path = Path::new(&CONFIG.tls_self_signed_key);
+
+    if cert_path.exists() && key_path.exists() {
+        info!("- Loading self-signed TLS certificate from {} ...", cert_path.display());
+        return Ok((read_certificate_chain(cert_path)?, read_private_key(key_path)?));
+    }
+
+    warn!("- No TLS certificate found, generating a self-signed certificate for localhost ...");
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])?;
+    write_pem(cert_path, &cert.serialize_pem()?, false)?;
+    write_pem(key_path, &cert.serialize_private_key_pem(), true)?;
+    info!("- Self-signed TLS certificate saved to {}", cert_path.display());
+
+    Ok((vec!(Certificate(cert.serialize_der()?)), PrivateKey(cert.serialize_private_key_der())))
+}
+
+fn read_certificate_chain(path: &Path) -> Result<Vec<Certificate>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let certs = rustls_pemfile::certs(&mut reader)?;
+
+    if certs.is_empty() {
+        return Err(Error::Tls(format!("No certificate found in {}", path.display())));
+    }
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn read_private_key(path: &Path) -> Result<PrivateKey> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    // Return the first key of the file whatever its format
+    while let Some(item) = rustls_pemfile::read_one(&mut reader)? {
+        match item {
+            Item::PKCS8Key(key) | Item::RSAKey(key) | Item::ECKey(key) => return Ok(PrivateKey(key)),
+            _ => continue,
+        }
+    }
+
+    Err(Error::Tls(format!("No private key found in {}", path.display())))
+}
+
+fn write_pem(path: &Path, content: &str, is_private: bool) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    // The private key must only be readable by the broker user
+    #[cfg(unix)]
+    if is_private {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    #[cfg(not(unix))]
+    let _ = is_private;
+
+    options.open(path)?.write_all(content.as_bytes())?;
+    Ok(())
+}
+
+/**
+ * This method return the SHA-256 fingerprint of a certificate
+ * formatted as "AB:CD:...", the same way as openssl.
+ *
+ * @param cert: &Certificate
+ *
+ * @return String
+ */
+pub fn fingerprint(cert: &Certificate) -> String {
+    Sha256::digest(&cert.0)
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<String>>()
+        .join(":")
+}
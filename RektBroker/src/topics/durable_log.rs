@@ -0,0 +1,621 @@
+// This document contain the durable topics of the broker. Each DtgData published
+// on a topic matching the durable_topics patterns of the config is appended to a
+// log on disk, so it survive a restart of the broker. The log of a topic is a
+// directory of segment files named by the index of their first message :
+//
+//     <log_directory>/<topic id>/00000000000000000000.log
+//
+// Segments only contain the DtgData as received, one after the other. Only the
+// last segment is written, a new one is started once it reach segment_size, and
+// the oldest ones are deleted according to the retention. All writes are done
+// by a single writer thread so the job system is never blocked by the disk.
+// On startup, the writer recover every log : a message partially written when
+// the broker stopped is truncated. A client can then resume a durable topic
+// from a sequence number, the messages are replayed through a stream.
+//
+// The log keep the messages in the order they were received. A resume start at
+// the first message whose sequence number is at least the requested one, and
+// send every message logged after it. Sequence numbers are chosen by the
+// publishers : the resume point is only meaningful for a topic published by a
+// single client whose sequence numbers increase without wrapping around.
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant, SystemTime};
+
+use bytes::Bytes;
+use quinn::Connection;
+use rekt_lib::datagrams::data_request::DtgData;
+use rekt_lib::enums::datagram_type::DatagramType;
+use rekt_lib::libs::types::TopicId;
+use rekt_lib::libs::utils::{get_u16_at_pos, get_u32_at_pos};
+
+use crate::{CONFIG, DURABLE_LOG};
+use crate::clients::client::ConnectionId;
+use crate::prelude::{Error, Result};
+
+const SEGMENT_EXTENSION: &str = "log";
+const WRITER_WAKE_PERIOD: Duration = Duration::from_millis(100);
+const RETENTION_CHECK_PERIOD: Duration = Duration::from_secs(60);
+// Messages of a segment read at once by a replay
+const REPLAY_CHUNK_SIZE: usize = 64 * 1024;
+// A DtgData start with [type u8][size u16][sequence number u32]
+const SIZE_OFFSET: usize = 1;
+const SEQUENCE_NUMBER_OFFSET: usize = 3;
+
+/**
+ * FsyncPolicy are all the way the log can be
+ * flushed to the disk.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FsyncPolicy {
+    Always, // Each message is synced before the next one is written
+    Periodic, // Written messages are synced every fsync_period
+    Never, // The OS decide when the messages reach the disk
+}
+
+impl FsyncPolicy {
+    /**
+     * This method convert the fsync_policy field of the config.
+     *
+     * @param value: &str, ex: "periodic"
+     *
+     * @return Option<FsyncPolicy>, None if the policy is unknown
+     */
+    pub fn from_name(value: &str) -> Option<FsyncPolicy> {
+        match value {
+            "always" => Some(FsyncPolicy::Always),
+            "periodic" => Some(FsyncPolicy::Periodic),
+            "never" => Some(FsyncPolicy::Never),
+            _ => None,
+        }
+    }
+}
+
+enum LogCommand {
+    Append(TopicId, Bytes),
+    Stop,
+}
+
+pub struct DurableLog {
+    sender: Sender<LogCommand>,
+    receiver: Mutex<Option<Receiver<LogCommand>>>, // taken by the writer thread
+}
+
+impl DurableLog {
+    pub fn new() -> DurableLog {
+        let (sender, receiver) = channel();
+        DurableLog {
+            sender,
+            receiver: Mutex::new(Some(receiver)),
+        }
+    }
+
+    pub fn is_durable(&self, topic_id: TopicId) -> bool {
+        CONFIG.durable_topics.iter().any(|pattern| pattern.matches(topic_id))
+    }
+
+    /**
+     * This method give a DtgData to the writer thread if its topic is durable.
+     * The datagram is copied so the pooled buffer it comes from can be reused.
+     *
+     * @param topic_id: TopicId
+     * @param datagram: &Bytes, the DtgData as received
+     */
+    pub fn append(&self, topic_id: TopicId, datagram: &Bytes) {
+        if self.is_durable(topic_id) && self.sender.send(LogCommand::Append(topic_id, Bytes::copy_from_slice(datagram))).is_err() {
+            warn!("The durable log is stopped, a message of topic {} is not saved.", topic_id);
+        }
+    }
+
+    /**
+     * This method ask the writer thread to sync every log and stop.
+     */
+    pub fn stop(&self) {
+        let _ = self.sender.send(LogCommand::Stop);
+    }
+
+    /**
+     * This method send the messages of a durable topic to a client, from a
+     * sequence number, through a new unidirectional stream. The segments are
+     * read one at a time in blocking tasks and written in the stream as they
+     * are read, so the whole log is never loaded in memory.
+     *
+     * @param target: ConnectionId, the subscriber
+     * @param connection: Connection, its QUIC connection
+     * @param topic_id: TopicId
+     * @param sequence_number: u32, the first sequence number to replay (included)
+     * @param on_empty: called once the replay is done if no message was replayed
+     */
+    pub fn replay(&self, target: ConnectionId, connection: Connection, topic_id: TopicId, sequence_number: u32, on_empty: impl FnOnce() + Send + 'static) {
+        tokio::spawn(async move {
+            match replay_segments(connection, topic_id, sequence_number).await {
+                Ok(replayed) => {
+                    if CONFIG.debug_topic_handler {
+                        debug!("{} messages of the durable topic {} replayed to {} from sequence {}", replayed, topic_id, target, sequence_number);
+                    }
+                    if replayed == 0 {
+                        on_empty();
+                    }
+                }
+                Err(err) => warn!("Failed to replay the durable topic {} to {} : {}", topic_id, target, err),
+            }
+        });
+    }
+}
+
+/**
+ * This method write the segments of a durable topic in a new unidirectional
+ * stream, starting with the one containing the sequence number.
+ *
+ * @param connection: Connection, the QUIC connection of the subscriber
+ * @param topic_id: TopicId
+ * @param sequence_number: u32, the first sequence number to replay (included)
+ *
+ * @return Result<u64>, the amount of messages replayed
+ */
+async fn replay_segments(connection: Connection, topic_id: TopicId, sequence_number: u32) -> Result<u64> {
+    let segments = tokio::task::spawn_blocking(move || segments_from(topic_id, sequence_number)).await
+        .map_err(|err| Error::Generic(err.to_string()))??;
+    if segments.is_empty() {
+        return Ok(0);
+    }
+
+    let mut stream = connection.open_uni().await?;
+    let mut replayed = 0;
+    for (position, path) in segments.into_iter().enumerate() {
+        // Only the first segment may contain messages before the sequence number
+        let from = (position == 0).then_some(sequence_number);
+        let mut segment = match tokio::task::spawn_blocking(move || SegmentReader::open(&path, from)).await
+            .map_err(|err| Error::Generic(err.to_string()))?? {
+            Some(segment) => segment,
+            None => continue,
+        };
+
+        loop {
+            // The reader is moved to the blocking task and given back with the messages read
+            let chunk;
+            (segment, chunk) = tokio::task::spawn_blocking(move || {
+                let chunk = segment.read_chunk(REPLAY_CHUNK_SIZE);
+                (segment, chunk)
+            }).await.map_err(|err| Error::Generic(err.to_string()))?;
+            let (count, content) = chunk?;
+            if content.is_empty() {
+                break;
+            }
+            stream.write_chunk(content).await?;
+            replayed += count;
+        }
+    }
+    stream.finish().await?;
+    Ok(replayed)
+}
+
+/**
+ * This method list the segments to replay from a sequence number : the last
+ * one whose first message has a sequence number lower or equal, and every
+ * following one. It is blocking.
+ *
+ * @param topic_id: TopicId
+ * @param sequence_number: u32
+ *
+ * @return Result<Vec<PathBuf>>, oldest first
+ */
+fn segments_from(topic_id: TopicId, sequence_number: u32) -> Result<Vec<PathBuf>> {
+    let directory = topic_directory(topic_id);
+    if !directory.exists() {
+        return Ok(Vec::new());
+    }
+
+    let segments = list_segments(&directory)?;
+    let start = segments.iter()
+        .rposition(|segment| first_sequence_number(&segment.path).is_some_and(|first| first <= sequence_number))
+        .unwrap_or(0);
+    Ok(segments.into_iter().skip(start).map(|segment| segment.path).collect())
+}
+
+// Read the sequence number of the first message of a segment, None if it is empty
+fn first_sequence_number(path: &Path) -> Option<u32> {
+    let mut header = vec![0; DtgData::get_default_byte_size()];
+    File::open(path).and_then(|mut file| file.read_exact(&mut header)).ok()?;
+    get_u32_at_pos(&header, SEQUENCE_NUMBER_OFFSET).ok()
+}
+
+/**
+ * SegmentReader read the complete messages of a segment a few at a time,
+ * so a replay never hold a whole segment in memory. A message being written
+ * by the writer thread is not complete yet : the read stop before it.
+ * All its methods are blocking.
+ */
+pub(crate) struct SegmentReader {
+    reader: BufReader<File>,
+    pending: Option<Vec<u8>>, // Message read while looking for the resume point, not sent yet
+}
+
+impl SegmentReader {
+    /**
+     * This method open a segment and skip its messages whose sequence
+     * number is lower than the given one.
+     *
+     * @param path: &Path, the segment
+     * @param sequence_number: Option<u32>, None to read the whole segment
+     *
+     * @return Result<Option<SegmentReader>>, None if the segment was deleted by the retention since it was listed
+     */
+    pub(crate) fn open(path: &Path, sequence_number: Option<u32>) -> Result<Option<SegmentReader>> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut segment = SegmentReader { reader: BufReader::new(file), pending: None };
+        if let Some(sequence_number) = sequence_number {
+            while let Some((record_sequence_number, record)) = segment.next_record()? {
+                if record_sequence_number >= sequence_number {
+                    segment.pending = Some(record);
+                    break;
+                }
+            }
+        }
+        Ok(Some(segment))
+    }
+
+    /**
+     * This method read the next messages of the segment, until they reach
+     * max_size or the end of the segment.
+     *
+     * @param max_size: usize, the messages read may exceed it by one message
+     *
+     * @return Result<(u64, Bytes)>, the amount of messages read and the messages, empty at the end of the segment
+     */
+    pub(crate) fn read_chunk(&mut self, max_size: usize) -> Result<(u64, Bytes)> {
+        let mut chunk = self.pending.take().unwrap_or_default();
+        let mut count = u64::from(!chunk.is_empty());
+        while chunk.len() < max_size {
+            match self.next_record()? {
+                Some((_, record)) => {
+                    chunk.extend_from_slice(&record);
+                    count += 1;
+                }
+                None => break,
+            }
+        }
+        Ok((count, Bytes::from(chunk)))
+    }
+
+    // Read the next complete DtgData and its sequence number, None at the end of the valid part of the segment
+    fn next_record(&mut self) -> Result<Option<(u32, Vec<u8>)>> {
+        let mut record = vec![0; DtgData::get_default_byte_size()];
+        if !read_complete(&mut self.reader, &mut record)? || DatagramType::from(record[0]) != DatagramType::Data {
+            return Ok(None);
+        }
+
+        let header_size = record.len();
+        let size = get_u16_at_pos(&record, SIZE_OFFSET).map_err(|err| Error::Generic(err.to_string()))?;
+        record.resize(header_size + size as usize, 0);
+        if !read_complete(&mut self.reader, &mut record[header_size..])? {
+            return Ok(None);
+        }
+        let sequence_number = get_u32_at_pos(&record, SEQUENCE_NUMBER_OFFSET).map_err(|err| Error::Generic(err.to_string()))?;
+        Ok(Some((sequence_number, record)))
+    }
+}
+
+// Fill the buffer, false if the file end before
+fn read_complete(reader: &mut impl Read, buffer: &mut [u8]) -> Result<bool> {
+    match reader.read_exact(buffer) {
+        Ok(()) => Ok(true),
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/**
+ * This method run the writer thread of the durable log. It recover every
+ * log found on the disk, then write the messages until the log is stopped.
+ * It is blocking and must be run in a blocking task.
+ */
+pub fn run_writer() {
+    let receiver = match DURABLE_LOG.receiver.lock().unwrap().take() {
+        Some(receiver) => receiver,
+        None => return, // already running
+    };
+
+    let mut logs = recover_logs();
+    info!("- Durable log started with {} topics recovered from {}.", logs.len(), CONFIG.durable_log_directory);
+
+    let fsync_period = Duration::from_secs(CONFIG.durable_fsync_period.into());
+    let mut last_sync = Instant::now();
+    let mut last_retention_check = Instant::now();
+
+    loop {
+        let mut stop = false;
+        match receiver.recv_timeout(WRITER_WAKE_PERIOD) {
+            Ok(command) => {
+                // Write everything already queued before flushing
+                for command in std::iter::once(command).chain(receiver.try_iter()) {
+                    match command {
+                        LogCommand::Append(topic_id, datagram) => write_message(&mut logs, topic_id, &datagram),
+                        LogCommand::Stop => stop = true,
+                    }
+                }
+                // Flushed messages are visible to the replays
+                for (topic_id, log) in logs.iter_mut() {
+                    let result = match CONFIG.durable_fsync_policy {
+                        FsyncPolicy::Always => log.sync(),
+                        _ => log.flush(),
+                    };
+                    if let Err(err) = result {
+                        error!("Failed to flush the durable log of topic {} : {}", topic_id, err);
+                    }
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => stop = true,
+        }
+
+        if stop {
+            break;
+        }
+
+        if CONFIG.durable_fsync_policy == FsyncPolicy::Periodic && last_sync.elapsed() >= fsync_period {
+            sync_logs(&mut logs);
+            last_sync = Instant::now();
+        }
+
+        if last_retention_check.elapsed() >= RETENTION_CHECK_PERIOD {
+            for (topic_id, log) in logs.iter_mut() {
+                log.apply_retention(*topic_id);
+            }
+            last_retention_check = Instant::now();
+        }
+    }
+
+    sync_logs(&mut logs);
+    info!("- Durable log stopped.");
+}
+
+fn write_message(logs: &mut HashMap<TopicId, TopicLog>, topic_id: TopicId, datagram: &[u8]) {
+    let log = match logs.entry(topic_id) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => match TopicLog::open(topic_id) {
+            Ok(log) => entry.insert(log),
+            Err(err) => {
+                error!("Failed to open the durable log of topic {}, the message is not saved : {}", topic_id, err);
+                return;
+            }
+        },
+    };
+
+    if let Err(err) = log.append(datagram) {
+        error!("Failed to write in the durable log of topic {} : {}", topic_id, err);
+    }
+}
+
+fn sync_logs(logs: &mut HashMap<TopicId, TopicLog>) {
+    for (topic_id, log) in logs.iter_mut() {
+        if let Err(err) = log.sync() {
+            error!("Failed to sync the durable log of topic {} : {}", topic_id, err);
+        }
+    }
+}
+
+// Open every log found in the log directory
+fn recover_logs() -> HashMap<TopicId, TopicLog> {
+    let mut logs: HashMap<TopicId, TopicLog> = HashMap::default();
+    let entries = match fs::read_dir(&CONFIG.durable_log_directory) {
+        Ok(entries) => entries,
+        Err(_) => return logs, // nothing saved yet
+    };
+
+    for entry in entries.flatten() {
+        let topic_id = match entry.file_name().to_str().and_then(|name| name.parse::<TopicId>().ok()) {
+            Some(topic_id) => topic_id,
+            None => continue,
+        };
+        match TopicLog::open(topic_id) {
+            Ok(mut log) => {
+                log.apply_retention(topic_id);
+                logs.insert(topic_id, log);
+            }
+            Err(err) => error!("Failed to recover the durable log of topic {} : {}", topic_id, err),
+        }
+    }
+    logs
+}
+
+#[derive(Debug)]
+struct Segment {
+    first_index: u64, // index of its first message in the topic log
+    path: PathBuf,
+    size: u64,
+}
+
+struct TopicLog {
+    directory: PathBuf,
+    segments: Vec<Segment>, // oldest first, the last one is written
+    writer: BufWriter<File>,
+    next_index: u64,
+    unsynced: bool,
+}
+
+impl TopicLog {
+    /**
+     * This method open the log of a topic, and create it if needed. The
+     * last segment is checked and truncated after its last complete message.
+     *
+     * @param topic_id: TopicId
+     *
+     * @return Result<TopicLog>
+     */
+    fn open(topic_id: TopicId) -> Result<TopicLog> {
+        let directory = topic_directory(topic_id);
+        fs::create_dir_all(&directory)?;
+
+        let mut segments = list_segments(&directory)?;
+        let next_index = match segments.last_mut() {
+            Some(last) => {
+                let (count, truncated) = recover_segment(&last.path)?;
+                if truncated > 0 {
+                    warn!("- Durable log of topic {} : {} bytes of an incomplete message truncated in {}",
+                        topic_id, truncated, last.path.display());
+                    last.size -= truncated;
+                }
+                last.first_index + count
+            }
+            None => {
+                let segment = Segment { first_index: 0, path: segment_path(&directory, 0), size: 0 };
+                segments.push(segment);
+                0
+            }
+        };
+
+        let writer = open_segment(&segments[segments.len() - 1].path)?;
+        Ok(TopicLog { directory, segments, writer, next_index, unsynced: false })
+    }
+
+    fn append(&mut self, datagram: &[u8]) -> Result<()> {
+        let size = datagram.len() as u64;
+        let active_size = self.segments.last().map_or(0, |segment| segment.size);
+        if active_size > 0 && active_size + size > CONFIG.durable_segment_size {
+            self.roll()?;
+        }
+
+        self.writer.write_all(datagram)?;
+        self.unsynced = true;
+        self.next_index += 1;
+        if let Some(active) = self.segments.last_mut() {
+            active.size += size;
+        }
+        Ok(())
+    }
+
+    // Close the active segment and start a new one
+    fn roll(&mut self) -> Result<()> {
+        if CONFIG.durable_fsync_policy == FsyncPolicy::Never {
+            self.flush()?;
+        } else {
+            self.sync()?;
+        }
+
+        let path = segment_path(&self.directory, self.next_index);
+        self.writer = open_segment(&path)?;
+        self.segments.push(Segment { first_index: self.next_index, path, size: 0 });
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        if self.unsynced {
+            self.writer.get_ref().sync_data()?;
+            self.unsynced = false;
+        }
+        Ok(())
+    }
+
+    // Delete the oldest segments according to the retention, the active segment is always kept
+    fn apply_retention(&mut self, topic_id: TopicId) {
+        let max_age = Duration::from_secs(CONFIG.durable_retention_duration);
+        let mut total_size: u64 = self.segments.iter().map(|segment| segment.size).sum();
+
+        while self.segments.len() > 1 {
+            let oldest = &self.segments[0];
+            let too_old = CONFIG.durable_retention_duration > 0 && fs::metadata(&oldest.path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+                .is_some_and(|age| age > max_age);
+            let too_big = CONFIG.durable_retention_size > 0 && total_size > CONFIG.durable_retention_size;
+            if !too_old && !too_big {
+                break;
+            }
+
+            if let Err(err) = fs::remove_file(&oldest.path) {
+                error!("Failed to delete the segment {} : {}", oldest.path.display(), err);
+                break;
+            }
+            if CONFIG.debug_topic_handler {
+                debug!("Segment {} of the durable topic {} deleted by the retention.", oldest.path.display(), topic_id);
+            }
+            total_size -= oldest.size;
+            self.segments.remove(0);
+        }
+    }
+}
+
+fn topic_directory(topic_id: TopicId) -> PathBuf {
+    Path::new(&CONFIG.durable_log_directory).join(topic_id.to_string())
+}
+
+fn segment_path(directory: &Path, first_index: u64) -> PathBuf {
+    directory.join(format!("{:020}.{}", first_index, SEGMENT_EXTENSION))
+}
+
+fn open_segment(path: &Path) -> Result<BufWriter<File>> {
+    Ok(BufWriter::new(OpenOptions::new().create(true).append(true).open(path)?))
+}
+
+// List the segments of a topic directory, oldest first
+fn list_segments(directory: &Path) -> Result<Vec<Segment>> {
+    let mut segments: Vec<Segment> = Vec::new();
+    for entry in fs::read_dir(directory)?.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|extension| extension.to_str()) != Some(SEGMENT_EXTENSION) {
+            continue;
+        }
+        let first_index = match path.file_stem().and_then(|stem| stem.to_str()).and_then(|stem| stem.parse::<u64>().ok()) {
+            Some(first_index) => first_index,
+            None => continue,
+        };
+        segments.push(Segment { first_index, size: entry.metadata()?.len(), path });
+    }
+    segments.sort_by_key(|segment| segment.first_index);
+    Ok(segments)
+}
+
+// Decode the message at the start of the buffer, None if it is incomplete
+fn read_record(buffer: &[u8]) -> Option<(DtgData, usize)> {
+    if buffer.first().map(|code| DatagramType::from(*code)) != Some(DatagramType::Data) {
+        return None;
+    }
+    let data = DtgData::try_from(buffer).ok()?;
+    let len = DtgData::get_default_byte_size() + data.size as usize;
+    Some((data, len))
+}
+
+// Count the complete messages of a segment, and the size they use
+pub(crate) fn count_records(content: &[u8]) -> (u64, usize) {
+    let (mut count, mut position) = (0, 0);
+    while let Some((_, len)) = read_record(&content[position..]) {
+        count += 1;
+        position += len;
+    }
+    (count, position)
+}
+
+/**
+ * This method truncate a segment after its last complete message.
+ *
+ * @param path: &Path, the segment
+ *
+ * @return Result<(u64, u64)>, the amount of complete messages and of bytes truncated
+ */
+pub(crate) fn recover_segment(path: &Path) -> Result<(u64, u64)> {
+    let content = fs::read(path)?;
+    let (count, valid_size) = count_records(&content);
+    if valid_size < content.len() {
+        OpenOptions::new().write(true).open(path)?.set_len(valid_size as u64)?;
+    }
+    Ok((count, (content.len() - valid_size) as u64))
+}
@@ -0,0 +1,5 @@
+pub mod durable_log;
+pub mod object_registry;
+pub mod retained_values;
+pub mod topic_history;
+pub mod topic_registry;
\ No newline at end of file
@@ -0,0 +1,199 @@
+// This document contain the object registry of the broker. An object is a set
+// of topics that clients can subscribe to in one request. Subscribing to an
+// object subscribe the client to each of its topics, and updating the object
+// add or remove these topic subscriptions for every object subscriber.
+//
+// Each object is locked while it is modified, so an update can't be
+// interleaved with a subscription to the same object.
+//
+// An object never give access to a topic the client isn't allowed to subscribe
+// to : the subscription to an object is denied if one of its topics is, and an
+// update only subscribe each subscriber to the added topics it has rights on.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use dashmap::DashMap;
+use dashmap::mapref::entry::Entry;
+use rekt_lib::libs::types::{ObjectId, TopicId};
+use rekt_lib::libs::utils::diff_hashsets;
+
+use crate::acl::Permissions;
+use crate::clients::client::ConnectionId;
+use crate::TOPIC_REGISTRY;
+
+// The 2 highest bits of an ObjectId identify who generated it
+const OBJECT_TYPE_MASK: ObjectId = 0b11 << 62;
+const USER_GENERATED_OBJECT: ObjectId = 0b00 << 62;
+const BROKER_GENERATED_OBJECT: ObjectId = 0b01 << 62;
+
+#[derive(Debug, Default)]
+struct Object {
+    topics: HashSet<TopicId>,
+    subscribers: HashMap<ConnectionId, Permissions>, // each subscriber is subscribed to the topics its rights allow
+}
+
+pub struct ObjectRegistry {
+    objects: DashMap<ObjectId, Object>, // <Object ID, topics and subscribers>
+    subscriptions: DashMap<ConnectionId, HashSet<ObjectId>>, // <Client, [Objects ID]>
+    next_id: AtomicU64, // identifier of the next broker generated object
+}
+
+impl ObjectRegistry {
+    pub fn new() -> ObjectRegistry {
+        ObjectRegistry {
+            objects: DashMap::default(),
+            subscriptions: DashMap::default(),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /**
+     * This method create an object. User generated identifiers are kept as is,
+     * temporary identifiers are replaced by a broker generated one.
+     *
+     * @param object_id: ObjectId, the identifier sent by the client
+     * @param topics: HashSet<TopicId>, the topics of the object
+     *
+     * @return Result<ObjectId, &str>, the final identifier of the object
+     */
+    pub fn create(&self, object_id: ObjectId, topics: HashSet<TopicId>) -> Result<ObjectId, &'static str> {
+        let final_object_id = if object_id & OBJECT_TYPE_MASK == USER_GENERATED_OBJECT {
+            object_id
+        } else {
+            BROKER_GENERATED_OBJECT | (self.next_id.fetch_add(1, Ordering::Relaxed) & !OBJECT_TYPE_MASK)
+        };
+
+        match self.objects.entry(final_object_id) {
+            Entry::Occupied(_) => Err("An object with this identifier already exists."),
+            Entry::Vacant(entry) => {
+                entry.insert(Object { topics, subscribers: HashMap::default() });
+                Ok(final_object_id)
+            }
+        }
+    }
+
+    /**
+     * This method replace the topics of an object. Every subscriber is subscribed
+     * to the added topics it is allowed to, and unsubscribed from the removed ones.
+     *
+     * @param object_id: ObjectId
+     * @param topics: HashSet<TopicId>, the new topics of the object
+     *
+     * @return Result<(), &str>
+     */
+    pub fn update(&self, object_id: ObjectId, topics: HashSet<TopicId>) -> Result<(), &'static str> {
+        let mut object = self.objects.get_mut(&object_id).ok_or("Unknown object.")?;
+        let (added, removed) = diff_hashsets(&topics, &object.topics);
+
+        for (client, permissions) in &object.subscribers {
+            for topic_id in added.iter().filter(|topic_id| permissions.can_subscribe(**topic_id)) {
+                TOPIC_REGISTRY.subscribe_from_object(*topic_id, *client);
+            }
+            for topic_id in removed.iter().filter(|topic_id| permissions.can_subscribe(**topic_id)) {
+                TOPIC_REGISTRY.unsubscribe_from_object(*topic_id, *client);
+            }
+        }
+        object.topics = topics;
+        Ok(())
+    }
+
+    /**
+     * This method delete an object and remove the
+     * topic subscriptions of all its subscribers.
+     *
+     * @param object_id: ObjectId
+     *
+     * @return Result<(), &str>
+     */
+    pub fn delete(&self, object_id: ObjectId) -> Result<(), &'static str> {
+        let (_, object) = self.objects.remove(&object_id).ok_or("Unknown object.")?;
+
+        for (client, permissions) in &object.subscribers {
+            for topic_id in object.topics.iter().filter(|topic_id| permissions.can_subscribe(**topic_id)) {
+                TOPIC_REGISTRY.unsubscribe_from_object(*topic_id, *client);
+            }
+            if let Some(mut objects) = self.subscriptions.get_mut(client) {
+                objects.remove(&object_id);
+            }
+            self.subscriptions.remove_if(client, |_, objects| objects.is_empty());
+        }
+        Ok(())
+    }
+
+    /**
+     * This method subscribe a client to an object and to each of its topics.
+     * Subscribing twice to the same object has no effect. The subscription
+     * is denied if the client isn't allowed to subscribe to every topic.
+     *
+     * @param object_id: ObjectId
+     * @param client: ConnectionId
+     * @param permissions: &Permissions, the rights of the client
+     *
+     * @return Result<(), &str>
+     */
+    pub fn subscribe(&self, object_id: ObjectId, client: ConnectionId, permissions: &Permissions) -> Result<(), &'static str> {
+        let mut object = self.objects.get_mut(&object_id).ok_or("Unknown object.")?;
+        if object.subscribers.contains_key(&client) {
+            return Ok(());
+        }
+        if !object.topics.iter().all(|topic_id| permissions.can_subscribe(*topic_id)) {
+            return Err("Not allowed to subscribe to every topic of this object.");
+        }
+        object.subscribers.insert(client, permissions.clone());
+
+        for topic_id in &object.topics {
+            TOPIC_REGISTRY.subscribe_from_object(*topic_id, client);
+        }
+        self.subscriptions.entry(client).or_default().insert(object_id);
+        Ok(())
+    }
+
+    /**
+     * This method unsubscribe a client from an object and from each of its topics.
+     *
+     * @param object_id: ObjectId
+     * @param client: ConnectionId
+     *
+     * @return Result<(), &str>
+     */
+    pub fn unsubscribe(&self, object_id: ObjectId, client: ConnectionId) -> Result<(), &'static str> {
+        let mut object = self.objects.get_mut(&object_id).ok_or("Unknown object.")?;
+        let permissions = object.subscribers.remove(&client).ok_or("Not subscribed to this object.")?;
+
+        for topic_id in object.topics.iter().filter(|topic_id| permissions.can_subscribe(**topic_id)) {
+            TOPIC_REGISTRY.unsubscribe_from_object(*topic_id, client);
+        }
+        if let Some(mut objects) = self.subscriptions.get_mut(&client) {
+            objects.remove(&object_id);
+        }
+        self.subscriptions.remove_if(&client, |_, objects| objects.is_empty());
+        Ok(())
+    }
+
+    /**
+     * This method remove a client from the subscribers of every object.
+     * Its topic subscriptions are cleaned by the topic registry.
+     *
+     * @param client: ConnectionId
+     *
+     * @return usize, the amount of objects the client was subscribed to
+     */
+    pub fn remove_client(&self, client: ConnectionId) -> usize {
+        let objects = match self.subscriptions.remove(&client) {
+            Some((_, objects)) => objects,
+            None => return 0,
+        };
+
+        for object_id in &objects {
+            if let Some(mut object) = self.objects.get_mut(object_id) {
+                object.subscribers.remove(&client);
+            }
+        }
+        objects.len()
+    }
+
+    pub fn object_count(&self) -> usize {
+        self.objects.len()
+    }
+}
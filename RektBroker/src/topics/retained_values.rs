@@ -0,0 +1,58 @@
+// This document contain the retained values of the broker. A retained topic
+// keep the last DtgData published on it, and this value is sent to each new
+// subscriber right after its subscription is acknowledged. Topics are retained
+// when they match the retained_topics patterns of the config, or once a client
+// subscribed to them with the TOPIC_OPTION_RETAINED option.
+
+use bytes::Bytes;
+use dashmap::DashMap;
+use rekt_lib::libs::types::TopicId;
+
+use crate::CONFIG;
+
+pub struct RetainedValues {
+    values: DashMap<TopicId, Option<Bytes>>, // <Topic ID, last DtgData>, a topic marked retained has an entry
+}
+
+impl RetainedValues {
+    pub fn new() -> RetainedValues {
+        RetainedValues {
+            values: DashMap::default(),
+        }
+    }
+
+    /**
+     * This method mark a topic as retained. Its next
+     * published value will be kept.
+     *
+     * @param topic_id: TopicId
+     */
+    pub fn mark(&self, topic_id: TopicId) {
+        self.values.entry(topic_id).or_insert(None);
+    }
+
+    pub fn is_retained(&self, topic_id: TopicId) -> bool {
+        self.values.contains_key(&topic_id)
+            || CONFIG.retained_topics.iter().any(|pattern| pattern.matches(topic_id))
+    }
+
+    /**
+     * This method keep a DtgData if its topic is retained. The datagram is
+     * copied so the pooled buffer it comes from can be reused.
+     *
+     * @param topic_id: TopicId
+     * @param datagram: &Bytes, the DtgData as received
+     */
+    pub fn store(&self, topic_id: TopicId, datagram: &Bytes) {
+        if self.is_retained(topic_id) {
+            self.values.insert(topic_id, Some(Bytes::copy_from_slice(datagram)));
+        }
+    }
+
+    /**
+     * @return Option<Bytes>, the last DtgData published on the topic, None if there is none
+     */
+    pub fn last_value(&self, topic_id: TopicId) -> Option<Bytes> {
+        self.values.get(&topic_id).and_then(|value| value.clone())
+    }
+}
@@ -0,0 +1,136 @@
+// This document contain the history of the broker topics. Each topic matching
+// the history_topics patterns of the config keep its last messages in a ring
+// buffer, bounded by history_size messages and history_duration seconds. A
+// client subscribing with the TOPIC_OPTION_HISTORY option receive this history
+// through a unidirectional stream : the DtgData are written one after the
+// other, unchanged, so their original sequence numbers are kept. The history is
+// taken while subscribing, so a message is either replayed or delivered live.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use dashmap::DashMap;
+use quinn::Connection;
+use rekt_lib::libs::types::TopicId;
+
+use crate::CONFIG;
+use crate::clients::client::ConnectionId;
+use crate::prelude::Result;
+
+pub struct TopicHistory {
+    histories: DashMap<TopicId, VecDeque<(Instant, Bytes)>>, // <Topic ID, [(reception time, DtgData)]> oldest first
+}
+
+impl TopicHistory {
+    pub fn new() -> TopicHistory {
+        TopicHistory {
+            histories: DashMap::default(),
+        }
+    }
+
+    pub fn has_history(&self, topic_id: TopicId) -> bool {
+        CONFIG.history_topics.iter().any(|pattern| pattern.matches(topic_id))
+    }
+
+    /**
+     * This method add a DtgData to the history of its topic, if the topic keep one,
+     * then call forward while the history is still locked : a subscription taken
+     * meanwhile see the message either in its history or through forward, not both.
+     * The datagram is copied so the pooled buffer it comes from can be reused.
+     *
+     * @param topic_id: TopicId
+     * @param datagram: &Bytes, the DtgData as received
+     * @param forward: FnOnce() -> T, reading the subscribers of the topic
+     *
+     * @return T, the result of forward
+     */
+    pub fn record<T>(&self, topic_id: TopicId, datagram: &Bytes, forward: impl FnOnce() -> T) -> T {
+        if !self.has_history(topic_id) {
+            return forward();
+        }
+
+        let mut history = self.histories.entry(topic_id).or_default();
+        if history.len() >= CONFIG.history_size {
+            history.pop_front();
+        }
+        history.push_back((Instant::now(), Bytes::copy_from_slice(datagram)));
+        forget_expired(&mut history);
+        forward()
+    }
+
+    /**
+     * This method call subscribe while the history of the topic is locked and
+     * return the history as it was at that time. The messages recorded after
+     * are delivered live to the new subscriber, so they must not be replayed.
+     *
+     * @param topic_id: TopicId
+     * @param subscribe: FnOnce() -> T, adding the subscriber to the topic
+     *
+     * @return (T, Vec<Bytes>), the result of subscribe and the DtgData of the history, oldest first
+     */
+    pub fn subscribe<T>(&self, topic_id: TopicId, subscribe: impl FnOnce() -> T) -> (T, Vec<Bytes>) {
+        if !self.has_history(topic_id) {
+            return (subscribe(), Vec::new());
+        }
+
+        let mut history = self.histories.entry(topic_id).or_default();
+        forget_expired(&mut history);
+        (subscribe(), history.iter().map(|(_, datagram)| datagram.clone()).collect())
+    }
+
+    /**
+     * This method send the history of a topic to a client through a new
+     * unidirectional stream. The stream is written in a separated task.
+     *
+     * @param target: ConnectionId, the subscriber
+     * @param connection: Connection, its QUIC connection
+     * @param topic_id: TopicId
+     * @param messages: Vec<Bytes>, the history taken when the client subscribed
+     *
+     * @return usize, the amount of messages replayed
+     */
+    pub fn replay(&self, target: ConnectionId, connection: Connection, topic_id: TopicId, messages: Vec<Bytes>) -> usize {
+        let count = messages.len();
+        if count == 0 {
+            return 0;
+        }
+
+        tokio::spawn(async move {
+            if let Err(err) = write_stream(connection, messages).await {
+                warn!("Failed to replay the history of topic {} to {} : {}", topic_id, target, err);
+            }
+        });
+        count
+    }
+}
+
+/**
+ * This method write datagrams one after the other in a new unidirectional
+ * stream, and finish it. The client split them using their size field.
+ *
+ * @param connection: Connection, the QUIC connection of the client
+ * @param messages: Vec<Bytes>, the datagrams to send
+ *
+ * @return Result<()>
+ */
+pub async fn write_stream(connection: Connection, messages: Vec<Bytes>) -> Result<()> {
+    let mut stream = connection.open_uni().await?;
+    for message in messages {
+        stream.write_all(&message).await?;
+    }
+    stream.finish().await?;
+    Ok(())
+}
+
+// Remove the messages older than history_duration
+fn forget_expired(history: &mut VecDeque<(Instant, Bytes)>) {
+    if CONFIG.history_duration == 0 {
+        return;
+    }
+
+    let max_age = Duration::from_secs(CONFIG.history_duration.into());
+    while history.front().is_some_and(|(received_at, _)| received_at.elapsed() > max_age) {
+        history.pop_front();
+    }
+}
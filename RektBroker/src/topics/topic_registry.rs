@@ -0,0 +1,200 @@
+// This document contain the topic registry of the broker. It store the
+// subscribers of each topic and, for each client, the topics it subscribed to.
+// The reverse map allow to clean every subscription of a client in one go when
+// its connection is closed. Both maps are concurrent so the job system workers
+// can read them without a global lock.
+//
+// A client can be subscribed to a topic directly and through any amount of
+// objects : it stay a subscriber of the topic until every reference is removed.
+// The subscribers of a topic stay locked while a subscription to it is changed,
+// so the two maps are always updated together.
+//
+// Each subscriber receive the topic with a delivery mode : the one asked by its
+// direct subscription, or else the default mode of the topic set in the config.
+
+use std::collections::HashMap;
+
+use dashmap::DashMap;
+use rekt_lib::enums::delivery_mode::DeliveryMode;
+use rekt_lib::libs::types::TopicId;
+
+use crate::CONFIG;
+use crate::clients::client::ConnectionId;
+
+// The references that keep a client subscribed to a topic
+#[derive(Debug, Default, Copy, Clone)]
+struct SubscriptionRefs {
+    direct: bool, // subscribed with a topic request
+    objects: u32, // amount of subscribed objects containing the topic
+    delivery_mode: Option<DeliveryMode>, // asked by the direct subscription, None for the default mode of the topic
+}
+
+impl SubscriptionRefs {
+    fn is_empty(&self) -> bool {
+        !self.direct && self.objects == 0
+    }
+}
+
+pub struct TopicRegistry {
+    subscribers: DashMap<TopicId, HashMap<ConnectionId, DeliveryMode>>, // <Topic ID, <Client, delivery mode>>
+    subscriptions: DashMap<ConnectionId, HashMap<TopicId, SubscriptionRefs>>, // <Client, <Topic ID, references>>
+}
+
+impl TopicRegistry {
+    pub fn new() -> TopicRegistry {
+        TopicRegistry {
+            subscribers: DashMap::default(),
+            subscriptions: DashMap::default(),
+        }
+    }
+
+    /**
+     * This method subscribe a client to a topic. Subscribing again
+     * replace the delivery mode of the previous subscription.
+     *
+     * @param topic_id: TopicId
+     * @param client: ConnectionId
+     * @param delivery_mode: Option<DeliveryMode>, None to use the default mode of the topic
+     *
+     * @return bool, false if the client was already subscribed
+     */
+    pub fn subscribe(&self, topic_id: TopicId, client: ConnectionId, delivery_mode: Option<DeliveryMode>) -> bool {
+        self.update(topic_id, client, |refs| {
+            refs.delivery_mode = delivery_mode;
+            !std::mem::replace(&mut refs.direct, true)
+        })
+    }
+
+    /**
+     * This method remove the direct subscription of a client to a topic. The
+     * client still receive the topic if it is subscribed to an object containing it.
+     *
+     * @param topic_id: TopicId
+     * @param client: ConnectionId
+     *
+     * @return bool, false if the client wasn't subscribed
+     */
+    pub fn unsubscribe(&self, topic_id: TopicId, client: ConnectionId) -> bool {
+        self.update(topic_id, client, |refs| {
+            refs.delivery_mode = None;
+            std::mem::replace(&mut refs.direct, false)
+        })
+    }
+
+    /**
+     * This method subscribe a client to a topic on behalf of an object.
+     *
+     * @param topic_id: TopicId
+     * @param client: ConnectionId
+     */
+    pub fn subscribe_from_object(&self, topic_id: TopicId, client: ConnectionId) {
+        self.update(topic_id, client, |refs| {
+            refs.objects += 1;
+            true
+        });
+    }
+
+    pub fn unsubscribe_from_object(&self, topic_id: TopicId, client: ConnectionId) {
+        self.update(topic_id, client, |refs| {
+            refs.objects = refs.objects.saturating_sub(1);
+            true
+        });
+    }
+
+    /**
+     * This method apply a change to the references of a subscription and then
+     * add or remove the client from the topic subscribers accordingly.
+     *
+     * @return bool, the value returned by the change
+     */
+    fn update(&self, topic_id: TopicId, client: ConnectionId, change: impl FnOnce(&mut SubscriptionRefs) -> bool) -> bool {
+        // Always locked before the subscriptions of the client, never after
+        let mut subscribers = self.subscribers.entry(topic_id).or_default();
+
+        let (changed, delivery_mode) = {
+            let mut topics = self.subscriptions.entry(client).or_default();
+            let refs = topics.entry(topic_id).or_default();
+            let changed = change(refs);
+            let delivery_mode = (!refs.is_empty()).then(|| refs.delivery_mode.unwrap_or_else(|| default_delivery_mode(topic_id)));
+            if delivery_mode.is_none() {
+                topics.remove(&topic_id);
+            }
+            (changed, delivery_mode)
+        };
+        self.subscriptions.remove_if(&client, |_, topics| topics.is_empty());
+
+        if let Some(delivery_mode) = delivery_mode {
+            subscribers.insert(client, delivery_mode);
+        } else {
+            subscribers.remove(&client);
+        }
+        drop(subscribers);
+
+        // Topics without subscribers are removed from the registry
+        self.subscribers.remove_if(&topic_id, |_, subscribers| subscribers.is_empty());
+        changed
+    }
+
+    /**
+     * This method return a copy of the subscribers of a topic, so
+     * no lock is kept while the datagrams are sent.
+     *
+     * @param topic_id: TopicId
+     *
+     * @return Vec<(ConnectionId, DeliveryMode)>, each subscriber and the way it receive the topic
+     */
+    pub fn subscribers_of(&self, topic_id: TopicId) -> Vec<(ConnectionId, DeliveryMode)> {
+        self.subscribers.get(&topic_id)
+            .map(|subscribers| subscribers.iter().map(|(client, delivery_mode)| (*client, *delivery_mode)).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn is_subscribed(&self, topic_id: TopicId, client: ConnectionId) -> bool {
+        self.delivery_mode_of(topic_id, client).is_some()
+    }
+
+    /**
+     * @return Option<DeliveryMode>, the way the client receive the topic, None if it isn't subscribed
+     */
+    pub fn delivery_mode_of(&self, topic_id: TopicId, client: ConnectionId) -> Option<DeliveryMode> {
+        self.subscribers.get(&topic_id)
+            .and_then(|subscribers| subscribers.get(&client).copied())
+    }
+
+    /**
+     * This method remove every subscription of a client, direct or through objects.
+     *
+     * @param client: ConnectionId
+     *
+     * @return usize, the amount of topics the client was subscribed to
+     */
+    pub fn remove_client(&self, client: ConnectionId) -> usize {
+        let topics: Vec<TopicId> = match self.subscriptions.get(&client) {
+            Some(topics) => topics.keys().copied().collect(),
+            None => return 0,
+        };
+
+        for topic_id in &topics {
+            self.update(*topic_id, client, |refs| {
+                *refs = SubscriptionRefs::default();
+                true
+            });
+        }
+        topics.len()
+    }
+
+    pub fn topic_count(&self) -> usize {
+        self.subscribers.len()
+    }
+}
+
+// The delivery mode of a topic set in the config, used when the subscription doesn't ask for one
+fn default_delivery_mode(topic_id: TopicId) -> DeliveryMode {
+    if CONFIG.reliable_ordered_topics.iter().any(|pattern| pattern.matches(topic_id)) {
+        DeliveryMode::ReliableOrdered
+    } else if CONFIG.reliable_unordered_topics.iter().any(|pattern| pattern.matches(topic_id)) {
+        DeliveryMode::ReliableUnordered
+    } else {
+        DeliveryMode::Unreliable
+    }
+}
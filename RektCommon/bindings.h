@@ -32,6 +32,9 @@ enum class DatagramType : uint8_t {
     ObjectRequestAck,
     ObjectRequestNack,
     Data,
+    DataWithAck,
+    DataAck,
+    DataNack,
     Unknown,
 };
 
@@ -39,6 +42,11 @@ enum class DatagramType : uint8_t {
 enum class EndConnexionReason : uint8_t {
     Shutdown,
     TimeOut,
+    RateLimited,
+    SlowConsumer,
+    BrokerFull,
+    Unauthorized,
+    Replaced,
     Unknown,
 };
 
@@ -112,17 +120,23 @@ struct ByteSlice {
 
 };
 
-struct DtgConnect {
+using Size = uint16_t;
+
+struct CDtgConnect {
     DatagramType datagram_type;
+    Size size;
+    VecU8 payload;
 
-    DtgConnect(DatagramType const& datagram_type)
-      : datagram_type(datagram_type)
+    CDtgConnect(DatagramType const& datagram_type,
+                Size const& size,
+                VecU8 const& payload)
+      : datagram_type(datagram_type),
+        size(size),
+        payload(payload)
     {}
 
 };
 
-using Size = uint16_t;
-
 struct CDtgConnectNack {
     DatagramType datagram_type;
     Size size;
@@ -140,6 +154,21 @@ struct CDtgConnectNack {
 
 using TopicId = uint64_t;
 
+struct DtgDataAck {
+    DatagramType datagram_type;
+    TopicId topic_id;
+    uint32_t sequence_number;
+
+    DtgDataAck(DatagramType const& datagram_type,
+               TopicId const& topic_id,
+               uint32_t const& sequence_number)
+      : datagram_type(datagram_type),
+        topic_id(topic_id),
+        sequence_number(sequence_number)
+    {}
+
+};
+
 struct CDtgData {
     DatagramType datagram_type;
     Size size;
@@ -293,6 +322,22 @@ struct DtgShutdown {
 
 };
 
+using TopicOptions = uint8_t;
+
+static const TopicOptions TOPIC_OPTION_HISTORY = 2;
+
+static const TopicOptions TOPIC_OPTION_NONE = 0;
+
+static const TopicOptions TOPIC_OPTION_RELIABLE_ORDERED = 16;
+
+static const TopicOptions TOPIC_OPTION_RELIABLE_UNORDERED = 24;
+
+static const TopicOptions TOPIC_OPTION_RESUME = 4;
+
+static const TopicOptions TOPIC_OPTION_RETAINED = 1;
+
+static const TopicOptions TOPIC_OPTION_UNRELIABLE = 8;
+
 
 extern "C" {
 
@@ -308,23 +353,39 @@ DtgConnectAck DtgConnectAckNew(ClientId peer_id, uint16_t heartbeat_period);
 
 const DtgConnectAck *DtgConnectAckTryFromBuffer(ByteSlice buffer);
 
-VecU8 DtgConnectAsBytes(DtgConnect datagram);
+VecU8 DtgConnectAsBytes(CDtgConnect datagram);
 
 VecU8 DtgConnectNackAsBytes(CDtgConnectNack datagram);
 
+///  * # Safety  * msg must be null or point to a nul-terminated string valid for the whole call.
 CDtgConnectNack DtgConnectNackNew(const char *msg);
 
 const CDtgConnectNack *DtgConnectNackTryFromBuffer(ByteSlice buffer);
 
-DtgConnect DtgConnectNew();
+///  * A null token is an anonymous connection, any other one is sent as is.  *  * # Safety  * token must be null or point to a nul-terminated string valid for the whole call.
+CDtgConnect DtgConnectNew(const char *token);
+
+///  * The datagram is written in out, owned by the caller. Return false if it is invalid or out is null.  *  * # Safety  * out must be null or point to a writable CDtgConnect. Its previous payload is overwritten without  * being freed, and the new one must be freed with vec_u8_free.
+bool DtgConnectTryFromBuffer(ByteSlice buffer,
+                             CDtgConnect *out);
 
-const DtgConnect *DtgConnectTryFromBuffer(ByteSlice buffer);
+VecU8 DtgDataAckAsBytes(DtgDataAck datagram);
+
+DtgDataAck DtgDataAckNew(TopicId topic_id, uint32_t sequence_number);
+
+bool DtgDataAckTryFromBuffer(ByteSlice buffer, DtgDataAck *out);
 
 VecU8 DtgDataAsBytes(CDtgData datagram);
 
+DtgDataAck DtgDataNackNew(TopicId topic_id, uint32_t sequence_number);
+
 CDtgData DtgDataNew(uint32_t sequence_number, TopicId topic_id, VecU8 payload);
 
-const CDtgData *DtgDataTryFromBuffer(ByteSlice buffer);
+///  * The datagram is written in out, owned by the caller. Return false if it is invalid or out is null.  *  * # Safety  * out must be null or point to a writable CDtgData. Its previous payload is overwritten without  * being freed, and the new one must be freed with vec_u8_free.
+bool DtgDataTryFromBuffer(ByteSlice buffer,
+                          CDtgData *out);
+
+CDtgData DtgDataWithAckNew(uint32_t sequence_number, TopicId topic_id, VecU8 payload);
 
 VecU8 DtgHeartbeatAsBytes(DtgHeartbeat datagram);
 
@@ -2,27 +2,39 @@ use std::mem::size_of;
 
 use crate::enums::datagram_type::DatagramType;
 use crate::libs::types::{ClientId, Size};
-use crate::libs::utils::{get_bytes_from_slice, get_u16_at_pos, get_u64_at_pos};
+use crate::libs::utils::{get_u16_at_pos, get_u64_at_pos};
 
-// Sent to the broker to start a connection
+// Sent to the broker to start a connection. The payload contain the
+// authentication token of the client, it is empty for anonymous clients.
 #[repr(C)]
 pub struct DtgConnect {
     pub datagram_type: DatagramType,
+    pub size: Size,
+    pub payload: Vec<u8>,
 }
 
 impl DtgConnect {
-    pub const fn new() -> DtgConnect {
+    pub fn new(token: &str) -> DtgConnect {
+        let token: Vec<u8> = token.as_bytes().into();
+
         DtgConnect {
-            datagram_type: DatagramType::Connect
+            datagram_type: DatagramType::Connect,
+            size: token.len() as Size,
+            payload: token,
         }
     }
 
     pub fn as_bytes(&self) -> Vec<u8>
     {
-        return [u8::from(self.datagram_type)].into();
+        let mut bytes: Vec<u8> = Vec::with_capacity(DtgConnect::get_default_byte_size() + self.size as usize);
+        bytes.push(u8::from(self.datagram_type));
+        bytes.extend(self.size.to_le_bytes());
+        bytes.extend(self.payload.iter());
+
+        return bytes;
     }
 
-    pub const fn get_default_byte_size() -> usize { return 1 }
+    pub const fn get_default_byte_size() -> usize { return 3 }
 }
 
 impl<'a> TryFrom<&'a [u8]> for DtgConnect {
@@ -33,9 +45,15 @@ impl<'a> TryFrom<&'a [u8]> for DtgConnect {
         {
             return Err("Payload len is to short for a DtgConnect.");
         }
+        let size = get_u16_at_pos(buffer, 1)?;
+        if buffer.len() < DtgConnect::get_default_byte_size() + size as usize {
+            return Err("Payload len is to short for the size announced by the DtgConnect.");
+        }
 
         Ok(DtgConnect {
             datagram_type: DatagramType::from(buffer[0]),
+            size,
+            payload: buffer[DtgConnect::get_default_byte_size()..DtgConnect::get_default_byte_size() + size as usize].into(),
         })
     }
 }
@@ -134,11 +152,14 @@ impl<'a> TryFrom<&'a [u8]> for DtgConnectNack {
             return Err("Payload len is to short for a RQ_Connect_Ack_Error.");
         }
         let size = get_u16_at_pos(buffer, 1)?;
+        if buffer.len() < DtgConnectNack::get_default_byte_size() + size as usize {
+            return Err("Payload len is to short for the size announced by the DtgConnectNack.");
+        }
 
         Ok(DtgConnectNack {
             datagram_type: DatagramType::from(buffer[0]),
             size,
-            payload: get_bytes_from_slice(buffer, DtgConnectNack::get_default_byte_size(), (DtgConnectNack::get_default_byte_size() + size as usize)),
+            payload: buffer[DtgConnectNack::get_default_byte_size()..DtgConnectNack::get_default_byte_size() + size as usize].into(),
         })
     }
 }
\ No newline at end of file
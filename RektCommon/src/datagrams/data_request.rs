@@ -23,6 +23,21 @@ impl DtgData {
         }
     }
 
+    /**
+     * This method ask the broker to acknowledge the DtgData with a DtgDataAck
+     * once accepted. The publisher retransmit it until then (QoS 1).
+     *
+     * @return DtgData
+     */
+    pub fn with_ack(mut self) -> DtgData {
+        self.datagram_type = DatagramType::DataWithAck;
+        self
+    }
+
+    pub fn requires_ack(&self) -> bool {
+        self.datagram_type == DatagramType::DataWithAck
+    }
+
     pub fn as_bytes(&self) -> Vec<u8>
     {
         let mut bytes: Vec<u8> = Vec::with_capacity(DtgData::get_default_byte_size() + self.size as usize);
@@ -45,15 +60,91 @@ impl<'a> TryFrom<&'a [u8]> for DtgData {
             return Err("Payload len is to short for a DtgData.");
         }
         let size = get_u16_at_pos(buffer, 1)?;
+        if buffer.len() < DtgData::get_default_byte_size() + size as usize {
+            return Err("Payload len is to short for the size announced by the DtgData.");
+        }
         let sequence_number = get_u32_at_pos(buffer, 3)?;
         let topic_id = get_u64_at_pos(buffer, 7)?;
+        let datagram_type = match DatagramType::from(buffer[0]) {
+            DatagramType::DataWithAck => DatagramType::DataWithAck,
+            _ => DatagramType::Data,
+        };
 
         Ok(DtgData {
-            datagram_type: DatagramType::Data,
+            datagram_type,
             size,
             sequence_number,
             topic_id,
             payload: buffer[DtgData::get_default_byte_size()..DtgData::get_default_byte_size() + size as usize].into(),
         })
     }
-}
\ No newline at end of file
+}
+
+//===== Sent by the broker to acknowledge a DtgData published with ack. The same
+// datagram with the DataNack type tell the publisher it was refused, it must not retransmit it
+#[repr(C)]
+pub struct DtgDataAck {
+    pub datagram_type: DatagramType, // 1 byte
+    pub topic_id: TopicId, // 8 bytes (u64)
+    pub sequence_number: u32, // 4 bytes (u32)
+}
+
+impl DtgDataAck {
+    pub const fn new(topic_id: TopicId, sequence_number: u32) -> DtgDataAck {
+        DtgDataAck {
+            datagram_type: DatagramType::DataAck,
+            topic_id,
+            sequence_number,
+        }
+    }
+
+    /**
+     * This method refuse the DtgData instead of acknowledging it,
+     * e.g. when the publisher has no right on its topic.
+     *
+     * @param topic_id: TopicId
+     * @param sequence_number: u32
+     *
+     * @return DtgDataAck
+     */
+    pub const fn nack(topic_id: TopicId, sequence_number: u32) -> DtgDataAck {
+        DtgDataAck {
+            datagram_type: DatagramType::DataNack,
+            topic_id,
+            sequence_number,
+        }
+    }
+
+    pub fn is_nack(&self) -> bool {
+        self.datagram_type == DatagramType::DataNack
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8>
+    {
+        let mut bytes: Vec<u8> = Vec::with_capacity(DtgDataAck::get_default_byte_size());
+        bytes.push(u8::from(self.datagram_type));
+        bytes.extend(self.topic_id.to_le_bytes());
+        bytes.extend(self.sequence_number.to_le_bytes());
+        bytes
+    }
+
+    pub const fn get_default_byte_size() -> usize { 13 }
+}
+
+impl<'a> TryFrom<&'a [u8]> for DtgDataAck {
+    type Error = &'a str;
+
+    fn try_from(buffer: &'a [u8]) -> Result<Self, Self::Error> {
+        if buffer.len() < DtgDataAck::get_default_byte_size() {
+            return Err("Payload len is to short for a DtgDataAck.");
+        }
+        let topic_id = get_u64_at_pos(buffer, 1)?;
+        let sequence_number = get_u32_at_pos(buffer, 9)?;
+
+        Ok(DtgDataAck {
+            datagram_type: DatagramType::from(buffer[0]),
+            topic_id,
+            sequence_number,
+        })
+    }
+}
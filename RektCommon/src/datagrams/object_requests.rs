@@ -56,6 +56,9 @@ impl<'a> TryFrom<&'a [u8]> for DtgObjectRequest {
             return Err("Payload len is to short for a DtgObjectRequest.");
         }
         let size = get_u16_at_pos(buffer, 1)?;
+        if buffer.len() < DtgObjectRequest::get_default_byte_size() + size as usize {
+            return Err("Payload len is to short for the size announced by the DtgObjectRequest.");
+        }
 
         let mut topics: HashSet<TopicId>;
         if size != 0 {
@@ -185,6 +188,9 @@ impl<'a> TryFrom<&'a [u8]> for DtgObjectRequestNACK {
             return Err("Payload len is to short for a DtgObjectRequestNACK.");
         }
         let size = get_u16_at_pos(buffer, 1)?;
+        if buffer.len() < DtgObjectRequestNACK::get_default_byte_size() + size as usize {
+            return Err("Payload len is to short for the size announced by the DtgObjectRequestNACK.");
+        }
         let object_id = get_u64_at_pos(buffer, 4)?;
 
         Ok(DtgObjectRequestNACK {
@@ -192,7 +198,7 @@ impl<'a> TryFrom<&'a [u8]> for DtgObjectRequestNACK {
             flag: buffer[3],
             size,
             object_id,
-            payload: get_bytes_from_slice(buffer, DtgObjectRequestNACK::get_default_byte_size(), (DtgObjectRequestNACK::get_default_byte_size() + size as usize - 1)),
+            payload: buffer[DtgObjectRequestNACK::get_default_byte_size()..DtgObjectRequestNACK::get_default_byte_size() + size as usize].into(),
         })
     }
 }
\ No newline at end of file
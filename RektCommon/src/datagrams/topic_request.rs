@@ -1,23 +1,75 @@
 use crate::enums::datagram_type::DatagramType;
+use crate::enums::delivery_mode::DeliveryMode;
 use crate::enums::topic_action::TopicAction;
 use crate::enums::topic_response::TopicResponse;
-use crate::libs::types::{Size, TopicId};
-use crate::libs::utils::{get_bytes_from_slice, get_u16_at_pos, get_u64_at_pos};
+use crate::libs::types::{Size, TopicId, TopicOptions};
+use crate::libs::utils::{get_u16_at_pos, get_u32_at_pos, get_u64_at_pos};
+
+// Options of a subscribe request, combined as bit flags
+pub const TOPIC_OPTION_NONE: TopicOptions = 0b0000_0000;
+pub const TOPIC_OPTION_RETAINED: TopicOptions = 0b0000_0001; // mark the topic as retained : its last value is kept and sent to new subscribers
+pub const TOPIC_OPTION_HISTORY: TopicOptions = 0b0000_0010; // ask for the replay of the topic history, sent through a stream
+pub const TOPIC_OPTION_RESUME: TopicOptions = 0b0000_0100; // ask for the replay of a durable topic from the resume_from sequence number, sent through a stream
+// The delivery mode of the subscription is a 2 bits field holding one of the values below, read with delivery_mode.
+// They aren't flags : RELIABLE_UNORDERED set both bits. Without it, the default mode of the topic is used
+pub const TOPIC_OPTION_UNRELIABLE: TopicOptions = 0b0000_1000; // receive the topic through QUIC datagrams
+pub const TOPIC_OPTION_RELIABLE_ORDERED: TopicOptions = 0b0001_0000; // receive the topic in order through the bidirectional stream
+pub const TOPIC_OPTION_RELIABLE_UNORDERED: TopicOptions = 0b0001_1000; // receive each message of the topic through its own stream
+const TOPIC_OPTION_DELIVERY_MASK: TopicOptions = 0b0001_1000;
 
 //===== Sent to subscribe/unsubscribe to a topic
 pub struct DtgTopicRequest {
     pub datagram_type: DatagramType, // 1 byte
     pub flag: TopicAction, // 1 byte
     pub topic_id: TopicId, // 8 bytes
+    pub options: TopicOptions, // 1 byte
+    pub resume_from: u32, // 4 bytes, first sequence number to replay, only used with TOPIC_OPTION_RESUME
 }
 
 //===== Sent to subscribe a topic
 impl DtgTopicRequest {
-    pub fn new(action: TopicAction, topic_id: TopicId) -> DtgTopicRequest {
+    pub fn new(action: TopicAction, topic_id: TopicId, options: TopicOptions) -> DtgTopicRequest {
         DtgTopicRequest {
             datagram_type: DatagramType::TopicRequest,
             flag: action,
-            topic_id
+            topic_id,
+            options,
+            resume_from: 0
+        }
+    }
+
+    /**
+     * This method ask for the replay of a durable topic, starting
+     * at the given sequence number (included).
+     *
+     * @param sequence_number: u32, the first sequence number to replay
+     *
+     * @return DtgTopicRequest
+     */
+    pub fn with_resume(mut self, sequence_number: u32) -> DtgTopicRequest {
+        self.options |= TOPIC_OPTION_RESUME;
+        self.resume_from = sequence_number;
+        self
+    }
+
+    /**
+     * @param option: TopicOptions, one or more TOPIC_OPTION_* flags
+     *
+     * @return bool, true if every flag is set. Always false for a delivery mode, use delivery_mode instead
+     */
+    pub const fn has_option(&self, option: TopicOptions) -> bool {
+        option & TOPIC_OPTION_DELIVERY_MASK == 0 && self.options & option == option
+    }
+
+    /**
+     * @return Option<DeliveryMode>, the delivery mode asked for the subscription, None to use the one of the topic
+     */
+    pub const fn delivery_mode(&self) -> Option<DeliveryMode> {
+        match self.options & TOPIC_OPTION_DELIVERY_MASK {
+            TOPIC_OPTION_UNRELIABLE => Some(DeliveryMode::Unreliable),
+            TOPIC_OPTION_RELIABLE_ORDERED => Some(DeliveryMode::ReliableOrdered),
+            TOPIC_OPTION_RELIABLE_UNORDERED => Some(DeliveryMode::ReliableUnordered),
+            _ => None,
         }
     }
 
@@ -27,11 +79,13 @@ impl DtgTopicRequest {
         bytes.push(u8::from(self.datagram_type));
         bytes.push(u8::from(self.flag));
         bytes.extend(self.topic_id.to_le_bytes());
+        bytes.push(self.options);
+        bytes.extend(self.resume_from.to_le_bytes());
         return bytes;
     }
 
 
-    pub const fn get_default_byte_size() -> usize { return 10; }
+    pub const fn get_default_byte_size() -> usize { return 15; }
 }
 
 impl<'a> TryFrom<&'a [u8]> for DtgTopicRequest{
@@ -42,11 +96,14 @@ impl<'a> TryFrom<&'a [u8]> for DtgTopicRequest{
             return Err("Payload len is to short for a DtgTopicRequest.");
         }
         let topic_id = get_u64_at_pos(buffer, 2)?;
+        let resume_from = get_u32_at_pos(buffer, 11)?;
 
         Ok(DtgTopicRequest {
             datagram_type: DatagramType::from(buffer[0]),
             flag: TopicAction::from(buffer[1]),
-            topic_id
+            topic_id,
+            options: buffer[10],
+            resume_from
         })
     }
 }
@@ -137,12 +194,15 @@ impl<'a> TryFrom<&'a [u8]> for DtgTopicRequestNack{
             return Err("Payload len is to short for a DtgTopicRequestNack.");
         }
         let size = get_u16_at_pos(buffer, 1)?;
+        if buffer.len() < DtgTopicRequestNack::get_default_byte_size() + size as usize {
+            return Err("Payload len is to short for the size announced by the DtgTopicRequestNack.");
+        }
 
         Ok(DtgTopicRequestNack {
             datagram_type: DatagramType::from(buffer[0]),
             flag: TopicResponse::from(buffer[3]),
             size,
-            payload: get_bytes_from_slice(buffer, DtgTopicRequestNack::get_default_byte_size(), (DtgTopicRequestNack::get_default_byte_size() + size as usize - 1))
+            payload: buffer[DtgTopicRequestNack::get_default_byte_size()..DtgTopicRequestNack::get_default_byte_size() + size as usize].into()
         })
     }
 }
\ No newline at end of file
@@ -28,6 +28,9 @@ pub enum DatagramType {
     ObjectRequestAck,
     ObjectRequestNack,
     Data,
+    DataWithAck,
+    DataAck,
+    DataNack,
     Unknown,
 }
 
@@ -58,6 +61,9 @@ pub fn display_datagram_type<'a>(datagram: DatagramType) -> &'a str{
         DatagramType::ObjectRequestAck => "Object_Request_Ack",
         DatagramType::ObjectRequestNack => "Object_Request_Nack",
         DatagramType::Data => "Data",
+        DatagramType::DataWithAck => "Data_With_Ack",
+        DatagramType::DataAck => "Data_Ack",
+        DatagramType::DataNack => "Data_Nack",
         DatagramType::Unknown => "Unknown",
     }
 }
@@ -91,6 +97,9 @@ impl From<u8> for DatagramType {
             0x08 => DatagramType::ObjectRequestAck,
             0x18 => DatagramType::ObjectRequestNack,
             0x42 => DatagramType::Data,
+            0x43 => DatagramType::DataWithAck,
+            0x02 => DatagramType::DataAck,
+            0x12 => DatagramType::DataNack,
             _ => DatagramType::Unknown
         }
     }
@@ -124,6 +133,9 @@ impl From<DatagramType> for u8 {
             DatagramType::ObjectRequestAck => 0x08,
             DatagramType::ObjectRequestNack => 0x18,
             DatagramType::Data => 0x42,
+            DatagramType::DataWithAck => 0x43,
+            DatagramType::DataAck => 0x02,
+            DatagramType::DataNack => 0x12,
             DatagramType::Unknown => 0xAA,
         }
     }
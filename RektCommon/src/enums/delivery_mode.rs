@@ -0,0 +1,47 @@
+/**
+ * Delivery modes are the ways a broker can forward
+ * the DtgData of a topic to one of its subscribers.
+ */
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[repr(u8)]
+#[no_mangle]
+pub enum DeliveryMode {
+    #[default]
+    Unreliable, // QUIC datagrams, may be lost or reordered
+    ReliableOrdered, // written one after the other in the bidirectional stream of the client
+    ReliableUnordered, // one unidirectional stream per message
+}
+
+/**
+ * This function convert a u8 to a DeliveryMode
+ *
+ * @param value: u8, The source to convert
+ *
+ * @return DeliveryMode
+ */
+impl From<u8> for DeliveryMode {
+    fn from(value: u8) -> Self {
+        match value {
+            0x01 => DeliveryMode::ReliableOrdered,
+            0x02 => DeliveryMode::ReliableUnordered,
+            _ => DeliveryMode::Unreliable,
+        }
+    }
+}
+
+/**
+ * This function convert a DeliveryMode to an u8
+ *
+ * @param value: DeliveryMode, The source to convert
+ *
+ * @return u8
+ */
+impl From<DeliveryMode> for u8 {
+    fn from(value: DeliveryMode) -> Self {
+        match value {
+            DeliveryMode::Unreliable => 0x00,
+            DeliveryMode::ReliableOrdered => 0x01,
+            DeliveryMode::ReliableUnordered => 0x02,
+        }
+    }
+}
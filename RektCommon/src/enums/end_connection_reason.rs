@@ -8,6 +8,11 @@
 pub enum EndConnexionReason {
     Shutdown,
     TimeOut,
+    RateLimited,
+    SlowConsumer,
+    BrokerFull,
+    Unauthorized,
+    Replaced, // a new connection from the same address took its place
     Unknown,
 }
 
@@ -23,6 +28,11 @@ impl From<u8> for EndConnexionReason {
         match value {
             0x00 => EndConnexionReason::Shutdown,
             0x01 => EndConnexionReason::TimeOut,
+            0x02 => EndConnexionReason::RateLimited,
+            0x03 => EndConnexionReason::SlowConsumer,
+            0x04 => EndConnexionReason::BrokerFull,
+            0x05 => EndConnexionReason::Unauthorized,
+            0x06 => EndConnexionReason::Replaced,
             _ => EndConnexionReason::Unknown,
         }
     }
@@ -40,6 +50,11 @@ impl From<EndConnexionReason> for u8 {
         match value {
             EndConnexionReason::Shutdown => 0x00,
             EndConnexionReason::TimeOut => 0x01,
+            EndConnexionReason::RateLimited => 0x02,
+            EndConnexionReason::SlowConsumer => 0x03,
+            EndConnexionReason::BrokerFull => 0x04,
+            EndConnexionReason::Unauthorized => 0x05,
+            EndConnexionReason::Replaced => 0x06,
             EndConnexionReason::Unknown => 0xAA,
         }
     }
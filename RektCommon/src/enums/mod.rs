@@ -1,4 +1,5 @@
 pub mod datagram_type;
+pub mod delivery_mode;
 pub mod object_request_action;
 pub mod end_connection_reason;
 pub mod topic_action;
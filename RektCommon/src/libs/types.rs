@@ -5,6 +5,7 @@ pub type Size = u16;
 pub type Flag = u8;
 // used to normalize the size of the enum used as flag
 pub type TopicId = u64;
+pub type TopicOptions = u8; // bit flags of a topic request (see topic_request.rs)
 pub type PingId = u8;
 pub type ObjectId = u64; // 0..2 for type identifier (User generated, broker, temporary)  2..64 identifier
 
@@ -7,7 +7,7 @@ use std::ptr::null;
 use std::str::{from_utf8, Utf8Error};
 
 use crate::datagrams::connect_requests::{DtgConnect, DtgConnectAck, DtgConnectNack};
-use crate::datagrams::data_request::DtgData;
+use crate::datagrams::data_request::{DtgData, DtgDataAck};
 use crate::datagrams::heartbeat_requests::{DtgHeartbeat, DtgHeartbeatRequest};
 use crate::datagrams::latency_requests::{DtgPing, DtgPong};
 use crate::datagrams::miscellaneous_requests::{DtgServerStatus, DtgServerStatusACK};
@@ -350,27 +350,86 @@ fn dtg_connect_nack_to_rust_type(dtg: CDtgConnectNack) -> DtgConnectNack
     }
 }
 
+#[repr(C)]
+pub struct CDtgConnect {
+    pub datagram_type: DatagramType,
+    pub size: Size,
+    pub payload: VecU8,
+}
+
+impl CDtgConnect {
+    pub fn new(payload: VecU8) -> CDtgConnect {
+        let token_size = payload.length as Size;
+
+        CDtgConnect {
+            datagram_type: DatagramType::Connect,
+            size: token_size,
+            payload,
+        }
+    }
+}
+
+fn dtg_connect_to_c_type(dtg: DtgConnect) -> CDtgConnect
+{
+    CDtgConnect::new(VecU8::from_vec(dtg.payload))
+}
+
+// The token is kept as bytes : the broker hash it without decoding it
+fn dtg_connect_to_rust_type(dtg: CDtgConnect) -> DtgConnect
+{
+    dtg_connect_from_token(dtg.payload.into_vec())
+}
+
+fn dtg_connect_from_token(token: Vec<u8>) -> DtgConnect
+{
+    DtgConnect {
+        datagram_type: DatagramType::Connect,
+        size: token.len() as Size,
+        payload: token,
+    }
+}
+
+/**
+ * A null token is an anonymous connection, any other one is sent as is.
+ *
+ * # Safety
+ * token must be null or point to a nul-terminated string valid for the whole call.
+ */
 #[no_mangle]
-pub extern "C" fn DtgConnectNew() -> DtgConnect
+pub unsafe extern "C" fn DtgConnectNew(token: *const c_char) -> CDtgConnect
 {
-    DtgConnect::new()
+    let token = if token.is_null() {
+        Vec::new()
+    } else {
+        CStr::from_ptr(token).to_bytes().to_vec()
+    };
+
+    dtg_connect_to_c_type(dtg_connect_from_token(token))
 }
 
 #[no_mangle]
-pub extern "C" fn DtgConnectAsBytes(datagram: DtgConnect) -> VecU8
+pub extern "C" fn DtgConnectAsBytes(datagram: CDtgConnect) -> VecU8
 {
-    VecU8::from_vec(datagram.as_bytes())
+    VecU8::from_vec(dtg_connect_to_rust_type(datagram).as_bytes())
 }
 
+/**
+ * The datagram is written in out, owned by the caller. Return false if it is invalid or out is null.
+ *
+ * # Safety
+ * out must be null or point to a writable CDtgConnect. Its previous payload is overwritten without
+ * being freed, and the new one must be freed with vec_u8_free.
+ */
 #[no_mangle]
-pub extern "C" fn DtgConnectTryFromBuffer(buffer: ByteSlice) -> *const DtgConnect
+pub unsafe extern "C" fn DtgConnectTryFromBuffer(buffer: ByteSlice, out: Option<&mut CDtgConnect>) -> bool
 {
-    match DtgConnect::try_from(buffer.as_slice()) {
-        Ok(dtg) => {
-            &dtg
+    match (DtgConnect::try_from(buffer.as_slice()), out) {
+        (Ok(dtg), Some(out)) => {
+            *out = dtg_connect_to_c_type(dtg);
+            true
         }
-        Err(_) => {
-            null()
+        _ => {
+            false
         }
     }
 }
@@ -400,18 +459,20 @@ pub extern "C" fn DtgConnectAckTryFromBuffer(buffer: ByteSlice) -> *const DtgCon
     }
 }
 
+/**
+ * # Safety
+ * msg must be null or point to a nul-terminated string valid for the whole call.
+ */
 #[no_mangle]
-pub extern "C" fn DtgConnectNackNew(msg: *const c_char) -> CDtgConnectNack
+pub unsafe extern "C" fn DtgConnectNackNew(msg: *const c_char) -> CDtgConnectNack
 {
-    let str_msg = unsafe {
-        if msg.is_null() {
-            ""
-        } else {
-            let cstr = CStr::from_ptr(msg);
-            match cstr.to_str().ok() {
-                None => { "" }
-                Some(val) => { val }
-            }
+    let str_msg = if msg.is_null() {
+        ""
+    } else {
+        let cstr = CStr::from_ptr(msg);
+        match cstr.to_str().ok() {
+            None => { "" }
+            Some(val) => { val }
         }
     };
 
@@ -470,12 +531,16 @@ impl CDtgData {
 
 fn dtg_data_to_c_type(dtg: DtgData) -> CDtgData
 {
-    CDtgData::new(dtg.sequence_number, dtg.topic_id, VecU8::from_vec(dtg.payload))
+    let mut c_dtg = CDtgData::new(dtg.sequence_number, dtg.topic_id, VecU8::from_vec(dtg.payload));
+    c_dtg.datagram_type = dtg.datagram_type;
+    c_dtg
 }
 
 fn dtg_data_to_rust_type(dtg: CDtgData) -> DtgData
 {
-    DtgData::new(dtg.sequence_number, dtg.topic_id, dtg.payload.into_vec())
+    let requires_ack = dtg.datagram_type == DatagramType::DataWithAck;
+    let rust_dtg = DtgData::new(dtg.sequence_number, dtg.topic_id, dtg.payload.into_vec());
+    if requires_ack { rust_dtg.with_ack() } else { rust_dtg }
 }
 
 #[no_mangle]
@@ -484,24 +549,70 @@ pub extern "C" fn DtgDataNew(sequence_number: u32, topic_id: TopicId, payload: V
     dtg_data_to_c_type(DtgData::new(sequence_number, topic_id, payload.into_vec()))
 }
 
+#[no_mangle]
+pub extern "C" fn DtgDataWithAckNew(sequence_number: u32, topic_id: TopicId, payload: VecU8) -> CDtgData
+{
+    dtg_data_to_c_type(DtgData::new(sequence_number, topic_id, payload.into_vec()).with_ack())
+}
+
 #[no_mangle]
 pub extern "C" fn DtgDataAsBytes(datagram: CDtgData) -> VecU8
 {
     VecU8::from_vec(dtg_data_to_rust_type(datagram).as_bytes())
 }
 
+/**
+ * The datagram is written in out, owned by the caller. Return false if it is invalid or out is null.
+ *
+ * # Safety
+ * out must be null or point to a writable CDtgData. Its previous payload is overwritten without
+ * being freed, and the new one must be freed with vec_u8_free.
+ */
 #[no_mangle]
-pub extern "C" fn DtgDataTryFromBuffer(buffer: ByteSlice) -> *const CDtgData
+pub unsafe extern "C" fn DtgDataTryFromBuffer(buffer: ByteSlice, out: Option<&mut CDtgData>) -> bool
 {
-    let dtg = match DtgData::try_from(buffer.as_slice()) {
-        Ok(dtg) => {
-            dtg
+    match (DtgData::try_from(buffer.as_slice()), out) {
+        (Ok(dtg), Some(out)) => {
+            *out = dtg_data_to_c_type(dtg);
+            true
         }
-        Err(_) => {
-            return null();
+        _ => {
+            false
         }
-    };
-    &dtg_data_to_c_type(dtg)
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn DtgDataAckNew(topic_id: TopicId, sequence_number: u32) -> DtgDataAck
+{
+    DtgDataAck::new(topic_id, sequence_number)
+}
+
+#[no_mangle]
+pub extern "C" fn DtgDataNackNew(topic_id: TopicId, sequence_number: u32) -> DtgDataAck
+{
+    DtgDataAck::nack(topic_id, sequence_number)
+}
+
+#[no_mangle]
+pub extern "C" fn DtgDataAckAsBytes(datagram: DtgDataAck) -> VecU8
+{
+    VecU8::from_vec(datagram.as_bytes())
+}
+
+// The datagram is written in out, owned by the caller. Return false if it is invalid or out is null
+#[no_mangle]
+pub extern "C" fn DtgDataAckTryFromBuffer(buffer: ByteSlice, out: Option<&mut DtgDataAck>) -> bool
+{
+    match (DtgDataAck::try_from(buffer.as_slice()), out) {
+        (Ok(dtg), Some(out)) => {
+            *out = dtg;
+            true
+        }
+        _ => {
+            false
+        }
+    }
 }
 
 
@@ -1,22 +1,26 @@
 #![allow(non_snake_case)]
 
 use std::collections::HashSet;
+use std::ffi::c_char;
 use std::mem::size_of;
+use std::ptr::null;
 use std::sync::Arc;
-use crate::datagrams::connect_requests::DtgConnect;
-use crate::datagrams::data_request::DtgData;
+use crate::datagrams::connect_requests::{DtgConnect, DtgConnectNack};
+use crate::datagrams::data_request::{DtgData, DtgDataAck};
 use crate::datagrams::heartbeat_requests::{DtgHeartbeat, DtgHeartbeatRequest};
 use crate::datagrams::latency_requests::{DtgPing, DtgPong};
 use crate::datagrams::miscellaneous_requests::{DtgServerStatus, DtgServerStatusACK};
 use crate::datagrams::object_requests::{DtgObjectRequest, DtgObjectRequestACK, DtgObjectRequestNACK};
 use crate::datagrams::shutdown_request::DtgShutdown;
-use crate::datagrams::topic_request::{DtgTopicRequest, DtgTopicRequestAck, DtgTopicRequestNack};
+use crate::datagrams::topic_request::{DtgTopicRequest, DtgTopicRequestAck, DtgTopicRequestNack, TOPIC_OPTION_HISTORY, TOPIC_OPTION_NONE, TOPIC_OPTION_RELIABLE_ORDERED, TOPIC_OPTION_RELIABLE_UNORDERED, TOPIC_OPTION_RESUME, TOPIC_OPTION_RETAINED, TOPIC_OPTION_UNRELIABLE};
 use crate::enums::datagram_type::DatagramType;
+use crate::enums::delivery_mode::DeliveryMode;
 use crate::enums::end_connection_reason::EndConnexionReason;
-use crate::enums::end_connection_reason::EndConnexionReason::{Shutdown, TimeOut};
+use crate::enums::end_connection_reason::EndConnexionReason::{BrokerFull, RateLimited, Shutdown, SlowConsumer, TimeOut, Unauthorized};
 use crate::enums::object_request_action::ObjectRequestAction;
 use crate::enums::topic_action::TopicAction;
 use crate::enums::topic_response::TopicResponse;
+use crate::rekt_common_ffi::{ByteSlice, CDtgData, DtgConnectNew, DtgConnectTryFromBuffer, DtgDataAckTryFromBuffer, DtgDataTryFromBuffer, vec_u8_free};
 use crate::libs::types::{ClientId, ObjectId, PingId, Size, TopicId};
 use crate::libs::utils::vec_to_u8;
 
@@ -25,14 +29,19 @@ use crate::libs::utils::vec_to_u8;
 // -------------------------------------------------------
 #[test]
 fn test_DtgConnect_as_bytes() {
-    let bytes: Vec<u8> = vec!(u8::from(DatagramType::Connect));
-    let dtg = DtgConnect::new();
+    let token = "secret-token";
+
+    let mut bytes: Vec<u8> = vec!(u8::from(DatagramType::Connect));
+    bytes.extend((token.len() as Size).to_le_bytes());
+    bytes.extend(token.as_bytes());
+
+    let dtg = DtgConnect::new(token);
     assert_eq!(dtg.as_bytes(), bytes);
 }
 
 #[test]
 fn test_DtgConnect_try_from() {
-    let dtg = Arc::from(DtgConnect::new());
+    let dtg = Arc::from(DtgConnect::new("secret-token"));
     let dtg_ref = dtg.clone().as_bytes();
     let dtg_from = DtgConnect::try_from(&*dtg_ref);
 
@@ -43,6 +52,76 @@ fn test_DtgConnect_try_from() {
     }
 }
 
+#[test]
+fn test_DtgConnect_try_from_anonymous() {
+    let bytes = DtgConnect::new("").as_bytes();
+
+    assert_eq!(bytes.len(), DtgConnect::get_default_byte_size());
+    assert_eq!(DtgConnect::try_from(&*bytes).unwrap().payload.len(), 0);
+}
+
+#[test]
+fn test_DtgConnect_try_from_truncated() {
+    let bytes = DtgConnect::new("secret-token").as_bytes();
+
+    assert!(DtgConnect::try_from(&bytes[..bytes.len() - 1]).is_err());
+}
+
+#[test]
+fn test_DtgConnectNew_non_utf8_token() {
+    // The token is not decoded : an invalid UTF-8 token must not become an anonymous connection
+    let token: &[u8] = b"\xff\xfe\0";
+    let dtg = unsafe { DtgConnectNew(token.as_ptr() as *const c_char) };
+
+    assert_eq!(dtg.size, 2);
+    vec_u8_free(dtg.payload);
+}
+
+#[test]
+fn test_DtgConnectTryFromBuffer() {
+    let bytes = DtgConnect::new("secret-token").as_bytes();
+
+    unsafe {
+        let mut dtg_from = DtgConnectNew(null());
+        assert!(DtgConnectTryFromBuffer(ByteSlice::new(&bytes), Some(&mut dtg_from)));
+        assert_eq!(dtg_from.datagram_type, DatagramType::Connect);
+        assert_eq!(dtg_from.size as usize, "secret-token".len());
+        vec_u8_free(dtg_from.payload);
+
+        let mut dtg_from = DtgConnectNew(null());
+        assert!(!DtgConnectTryFromBuffer(ByteSlice::new(&bytes[..bytes.len() - 1]), Some(&mut dtg_from)));
+        assert!(!DtgConnectTryFromBuffer(ByteSlice::new(&bytes), None));
+        vec_u8_free(dtg_from.payload);
+    }
+}
+
+#[test]
+fn test_DtgConnectNack_try_from() {
+    let dtg = DtgConnectNack::new("The broker is full.");
+    let bytes = dtg.as_bytes();
+    let dtg_from = DtgConnectNack::try_from(&*bytes);
+
+    if dtg_from.is_ok() {
+        assert_eq!(dtg_from.unwrap().as_bytes(), bytes);
+    }else {
+        assert!(false, "dtg_from is invalid");
+    }
+}
+
+#[test]
+fn test_DtgConnectNack_try_from_empty_reason() {
+    let bytes = DtgConnectNack::new("").as_bytes();
+
+    assert_eq!(DtgConnectNack::try_from(&*bytes).unwrap().payload.len(), 0);
+}
+
+#[test]
+fn test_DtgConnectNack_try_from_truncated() {
+    let bytes = DtgConnectNack::new("The broker is full.").as_bytes();
+
+    assert!(DtgConnectNack::try_from(&bytes[..bytes.len() - 1]).is_err());
+}
+
 // -------------------------------------------------------
 //   Shutdown
 // -------------------------------------------------------
@@ -54,6 +133,35 @@ fn test_DtgShutdown_as_bytes() {
     let bytes: Vec<u8> = vec!(u8::from(DatagramType::Shutdown), u8::from(TimeOut));
     let dtg = DtgShutdown::new(TimeOut);
     assert_eq!(dtg.as_bytes(), bytes);
+    let bytes: Vec<u8> = vec!(u8::from(DatagramType::Shutdown), 0x02);
+    let dtg = DtgShutdown::new(RateLimited);
+    assert_eq!(dtg.as_bytes(), bytes);
+}
+
+#[test]
+fn test_DtgShutdown_try_from_rate_limited() {
+    let bytes: Vec<u8> = vec!(u8::from(DatagramType::Shutdown), 0x02);
+    let dtg = DtgShutdown::try_from(&bytes[..]).unwrap();
+    assert_eq!(dtg.reason, RateLimited);
+}
+
+#[test]
+fn test_DtgShutdown_try_from_slow_consumer() {
+    let bytes: Vec<u8> = vec!(u8::from(DatagramType::Shutdown), 0x03);
+    let dtg = DtgShutdown::try_from(&bytes[..]).unwrap();
+    assert_eq!(dtg.reason, SlowConsumer);
+    assert_eq!(u8::from(SlowConsumer), 0x03);
+}
+
+#[test]
+fn test_EndConnexionReason_refusals() {
+    assert_eq!(EndConnexionReason::from(0x04), BrokerFull);
+    assert_eq!(EndConnexionReason::from(0x05), Unauthorized);
+    assert_eq!(u8::from(BrokerFull), 0x04);
+    assert_eq!(u8::from(Unauthorized), 0x05);
+    assert_eq!(EndConnexionReason::from(0x06), EndConnexionReason::Replaced);
+    assert_eq!(u8::from(EndConnexionReason::Replaced), 0x06);
+    assert_eq!(EndConnexionReason::from(0x07), EndConnexionReason::Unknown);
 }
 
 #[test]
@@ -236,6 +344,102 @@ fn test_DtgData_try_from() {
     }
 }
 
+#[test]
+fn test_DtgData_try_from_truncated() {
+    let dtg = DtgData::new(1, 2 as TopicId, b"Message tronque".to_vec());
+    let bytes = dtg.as_bytes();
+
+    assert!(DtgData::try_from(&bytes[..bytes.len() - 1]).is_err());
+}
+
+#[test]
+fn test_DtgData_with_ack() {
+    let dtg = DtgData::new(42, 444 as TopicId, b"Message avec accuse de reception".to_vec()).with_ack();
+    assert!(dtg.requires_ack());
+
+    let bytes = dtg.as_bytes();
+    assert_eq!(DatagramType::from(bytes[0]), DatagramType::DataWithAck);
+
+    let dtg_from = DtgData::try_from(&bytes[..]).unwrap();
+    assert!(dtg_from.requires_ack());
+    assert_eq!(dtg_from.as_bytes(), bytes);
+
+    let dtg = DtgData::new(42, 444 as TopicId, Vec::new());
+    assert!(!DtgData::try_from(&dtg.as_bytes()[..]).unwrap().requires_ack());
+}
+
+#[test]
+fn test_DtgDataTryFromBuffer() {
+    let bytes = DtgData::new(42, 444 as TopicId, b"payload".to_vec()).with_ack().as_bytes();
+
+    unsafe {
+        // Nothing to free in a zeroed CDtgData : its payload is null
+        let mut dtg_from: CDtgData = std::mem::zeroed();
+        assert!(DtgDataTryFromBuffer(ByteSlice::new(&bytes), Some(&mut dtg_from)));
+        assert_eq!(dtg_from.datagram_type, DatagramType::DataWithAck);
+        assert_eq!(dtg_from.sequence_number, 42);
+        assert_eq!(dtg_from.topic_id, 444);
+        assert_eq!(dtg_from.size as usize, b"payload".len());
+        vec_u8_free(dtg_from.payload);
+
+        let mut dtg_from: CDtgData = std::mem::zeroed();
+        assert!(!DtgDataTryFromBuffer(ByteSlice::new(&bytes[..bytes.len() - 1]), Some(&mut dtg_from)));
+        assert!(!DtgDataTryFromBuffer(ByteSlice::new(&bytes), None));
+    }
+}
+
+#[test]
+fn test_DtgDataAck_as_bytes() {
+    let topicID = 444 as TopicId;
+    let sequenceNB: u32 = 654674698;
+
+    let mut bytes: Vec<u8> = Vec::new();
+    bytes.push(u8::from(DatagramType::DataAck));
+    bytes.extend(topicID.to_le_bytes());
+    bytes.extend(sequenceNB.to_le_bytes());
+
+    let dtg = DtgDataAck::new(topicID, sequenceNB);
+    assert_eq!(dtg.as_bytes(), bytes);
+}
+
+#[test]
+fn test_DtgDataAck_try_from() {
+    let dtg = DtgDataAck::new(44687687696844 as TopicId, 654674698);
+    let bytes = dtg.as_bytes();
+
+    let dtg_from = DtgDataAck::try_from(&bytes[..]).unwrap();
+    assert_eq!(dtg_from.datagram_type, DatagramType::DataAck);
+    assert_eq!(dtg_from.topic_id, dtg.topic_id);
+    assert_eq!(dtg_from.sequence_number, dtg.sequence_number);
+    assert!(DtgDataAck::try_from(&bytes[..bytes.len() - 1]).is_err());
+}
+
+#[test]
+fn test_DtgDataAck_nack_try_from() {
+    let bytes = DtgDataAck::nack(444 as TopicId, 17).as_bytes();
+    assert_eq!(bytes[0], 0x12);
+
+    let dtg_from = DtgDataAck::try_from(&bytes[..]).unwrap();
+    assert_eq!(dtg_from.datagram_type, DatagramType::DataNack);
+    assert!(dtg_from.is_nack());
+    assert_eq!(dtg_from.topic_id, 444);
+    assert_eq!(dtg_from.sequence_number, 17);
+    assert!(!DtgDataAck::new(444 as TopicId, 17).is_nack());
+}
+
+#[test]
+fn test_DtgDataAckTryFromBuffer() {
+    let bytes = DtgDataAck::new(987 as TopicId, 42).as_bytes();
+
+    let mut dtg_from = DtgDataAck::new(0, 0);
+    assert!(DtgDataAckTryFromBuffer(ByteSlice::new(&bytes), Some(&mut dtg_from)));
+    assert_eq!(dtg_from.topic_id, 987);
+    assert_eq!(dtg_from.sequence_number, 42);
+
+    assert!(!DtgDataAckTryFromBuffer(ByteSlice::new(&bytes[..bytes.len() - 1]), Some(&mut dtg_from)));
+    assert!(!DtgDataAckTryFromBuffer(ByteSlice::new(&bytes), None));
+}
+
 // -------------------------------------------------------
 //   ObjectRequest datagrams
 // -------------------------------------------------------
@@ -281,6 +485,14 @@ fn test_DtgObjectRequest_try_from() {
     }
 }
 
+#[test]
+fn test_DtgObjectRequest_try_from_truncated() {
+    let dtg = DtgObjectRequest::new(ObjectRequestAction::Create, 1 as ObjectId, HashSet::from([1, 2, 3]));
+    let bytes = dtg.as_bytes();
+
+    assert!(DtgObjectRequest::try_from(&bytes[..bytes.len() - 8]).is_err());
+}
+
 #[test]
 fn test_DtgObjectRequestACK_as_bytes() {
     let flag = vec_to_u8(vec!(0,0,0,0,0,1,0,0)); // delete action
@@ -383,8 +595,10 @@ fn test_DtgTopicRequest_as_bytes() {
     bytes.push(u8::from(DatagramType::TopicRequest));
     bytes.push(u8::from(TopicAction));
     bytes.extend(TopicsId.to_le_bytes());
+    bytes.push(TOPIC_OPTION_RETAINED);
+    bytes.extend(0u32.to_le_bytes());
 
-    let dtg = DtgTopicRequest::new(TopicAction, TopicsId);
+    let dtg = DtgTopicRequest::new(TopicAction, TopicsId, TOPIC_OPTION_RETAINED);
     assert_eq!(dtg.as_bytes(), bytes);
 }
 
@@ -393,7 +607,7 @@ fn test_DtgOTopicRequest_try_from() {
     let TopicAction = TopicAction::Subscribe;
     let TopicsId = 641635874654 as TopicId;
 
-    let dtg = Arc::from(DtgTopicRequest::new(TopicAction, TopicsId));
+    let dtg = Arc::from(DtgTopicRequest::new(TopicAction, TopicsId, TOPIC_OPTION_NONE));
     let dtg_ref = dtg.clone().as_bytes();
     let ResultDtg_from = DtgTopicRequest::try_from(&*dtg_ref);
 
@@ -404,6 +618,62 @@ fn test_DtgOTopicRequest_try_from() {
     }
 }
 
+#[test]
+fn test_DtgTopicRequest_options() {
+    let dtg = DtgTopicRequest::new(TopicAction::Subscribe, 42, TOPIC_OPTION_RETAINED);
+    let dtg_from = DtgTopicRequest::try_from(&dtg.as_bytes()[..]).unwrap();
+    assert!(dtg_from.has_option(TOPIC_OPTION_RETAINED));
+    assert!(!dtg_from.has_option(TOPIC_OPTION_HISTORY));
+
+    let dtg = DtgTopicRequest::new(TopicAction::Subscribe, 42, TOPIC_OPTION_RETAINED | TOPIC_OPTION_HISTORY);
+    let dtg_from = DtgTopicRequest::try_from(&dtg.as_bytes()[..]).unwrap();
+    assert!(dtg_from.has_option(TOPIC_OPTION_RETAINED));
+    assert!(dtg_from.has_option(TOPIC_OPTION_HISTORY));
+    assert!(dtg_from.has_option(TOPIC_OPTION_RETAINED | TOPIC_OPTION_HISTORY));
+
+    let dtg = DtgTopicRequest::new(TopicAction::Subscribe, 42, TOPIC_OPTION_NONE);
+    assert!(!dtg.has_option(TOPIC_OPTION_RETAINED));
+}
+
+#[test]
+fn test_DtgTopicRequest_with_resume() {
+    let dtg = DtgTopicRequest::new(TopicAction::Subscribe, 42, TOPIC_OPTION_RETAINED).with_resume(1337);
+    assert!(dtg.has_option(TOPIC_OPTION_RETAINED | TOPIC_OPTION_RESUME));
+
+    let dtg_from = DtgTopicRequest::try_from(&dtg.as_bytes()[..]).unwrap();
+    assert!(dtg_from.has_option(TOPIC_OPTION_RESUME));
+    assert_eq!(dtg_from.resume_from, 1337);
+    assert_eq!(dtg_from.as_bytes(), dtg.as_bytes());
+}
+
+#[test]
+fn test_DtgTopicRequest_delivery_mode() {
+    let dtg = DtgTopicRequest::new(TopicAction::Subscribe, 42, TOPIC_OPTION_RETAINED);
+    assert_eq!(dtg.delivery_mode(), None);
+
+    let dtg = DtgTopicRequest::new(TopicAction::Subscribe, 42, TOPIC_OPTION_UNRELIABLE);
+    assert_eq!(dtg.delivery_mode(), Some(DeliveryMode::Unreliable));
+
+    let dtg = DtgTopicRequest::new(TopicAction::Subscribe, 42, TOPIC_OPTION_RETAINED | TOPIC_OPTION_RELIABLE_ORDERED);
+    let dtg_from = DtgTopicRequest::try_from(&dtg.as_bytes()[..]).unwrap();
+    assert_eq!(dtg_from.delivery_mode(), Some(DeliveryMode::ReliableOrdered));
+    assert!(dtg_from.has_option(TOPIC_OPTION_RETAINED));
+
+    let dtg = DtgTopicRequest::new(TopicAction::Subscribe, 42, TOPIC_OPTION_HISTORY | TOPIC_OPTION_RELIABLE_UNORDERED);
+    let dtg_from = DtgTopicRequest::try_from(&dtg.as_bytes()[..]).unwrap();
+    assert_eq!(dtg_from.delivery_mode(), Some(DeliveryMode::ReliableUnordered));
+    assert!(dtg_from.has_option(TOPIC_OPTION_HISTORY));
+    // The delivery mode bits aren't flags
+    assert!(!dtg_from.has_option(TOPIC_OPTION_UNRELIABLE));
+    assert!(!dtg_from.has_option(TOPIC_OPTION_RELIABLE_ORDERED));
+}
+
+#[test]
+fn test_DtgTopicRequest_try_from_truncated() {
+    let bytes = DtgTopicRequest::new(TopicAction::Subscribe, 42, TOPIC_OPTION_NONE).as_bytes();
+    assert!(DtgTopicRequest::try_from(&bytes[..bytes.len() - 1]).is_err());
+}
+
 #[test]
 fn test_DtgTopicRequestACK_as_bytes() {
     let flag = TopicResponse::SubFailure;
@@ -463,4 +733,11 @@ fn test_DtgTopicRequestNACK_try_from() {
     }else {
         assert!(false, "dtg_from is invalid");
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_DtgTopicRequestNACK_try_from_truncated() {
+    let bytes = DtgTopicRequestNack::new(TopicResponse::SubFailure, "Unknown topic.").as_bytes();
+
+    assert!(DtgTopicRequestNack::try_from(&bytes[..bytes.len() - 1]).is_err());
+}